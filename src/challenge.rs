@@ -0,0 +1,532 @@
+use bevy::prelude::*;
+use std::{collections::HashSet, fmt};
+use crate::{
+    clipboard, ButtonAction, ButtonActionEvent, Game, GameMode, GameSpec, MergeRules, Position,
+    Puzzle, PuzzleGoal, RunState, Spawns, BOARD_SIZES, MAX_VALUE,
+};
+
+/// Bumped whenever the layout of a code changes, so codes from other
+/// versions are refused rather than read wrongly.
+const CODE_VERSION: u8 = 1;
+
+/// Letters used for codes: Crockford's base 32, which leaves out the ones
+/// easily mistaken for each other.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Characters between the dashes that split a code up for reading out.
+const GROUP_LENGTH: usize = 4;
+
+/// Longest code that can be typed in.
+const CODE_LENGTH: usize = 320;
+
+/// Modes in the order codes number them. New modes go on the end.
+const MODES: [GameMode; 7] = [
+    GameMode::Classic,
+    GameMode::Hardcore,
+    GameMode::Practice,
+    GameMode::Blitz,
+    GameMode::Puzzle,
+    GameMode::Daily,
+    GameMode::Zen,
+];
+
+/// Why a challenge code couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeError {
+    InvalidCharacter(char),
+    /// The code was mistyped or cut short.
+    Checksum,
+    UnknownVersion(u8),
+    /// The code reads fine but describes a game that can't be played.
+    Invalid,
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodeError::InvalidCharacter(character) => {
+                write!(f, "'{}' can't be part of a code", character)
+            }
+            CodeError::Checksum => write!(f, "that code has a typo or is incomplete"),
+            CodeError::UnknownVersion(_) => {
+                write!(f, "that code is from a different version of the game")
+            }
+            CodeError::Invalid => write!(f, "that code isn't a playable game"),
+        }
+    }
+}
+
+/// Turns a game's setup into a short code that plays exactly the same game
+/// when imported with [`decode`]. Only the day's own attempt counts for a
+/// daily challenge, so it's shared as a classic game on the same board.
+pub fn encode(spec: &GameSpec) -> String {
+    let mut bytes = vec![CODE_VERSION, spec.board_size];
+    let mode = match spec.mode {
+        GameMode::Daily => GameMode::Classic,
+        mode => mode,
+    };
+    let mode = MODES.iter().position(|other| *other == mode).unwrap_or(0);
+    bytes.push(mode as u8);
+    bytes.push((spec.four_chance * 100.0).round() as u8);
+    match spec.puzzle {
+        None => bytes.push(0),
+        Some(Puzzle { moves, goal }) => {
+            let (kind, target) = match goal {
+                PuzzleGoal::Tile(tile) => (1, tile),
+                PuzzleGoal::Score(score) => (2, score),
+            };
+            bytes.push(kind);
+            push_varint(&mut bytes, u64::from(moves));
+            push_varint(&mut bytes, u64::from(target));
+        }
+    }
+    match &spec.spawns {
+        Spawns::Random => bytes.push(0),
+        Spawns::Seed(seed) => {
+            bytes.push(1);
+            push_varint(&mut bytes, *seed);
+        }
+        Spawns::Sequence(tiles) => {
+            bytes.push(2);
+            push_tiles(&mut bytes, spec.board_size, tiles);
+        }
+    }
+    push_tiles(&mut bytes, spec.board_size, &spec.tiles);
+    push_varint(&mut bytes, u64::from(spec.score));
+    push_varint(&mut bytes, spec.blockers.len() as u64);
+    for position in &spec.blockers {
+        bytes.push(position.y * spec.board_size + position.x);
+    }
+    bytes.push(u8::from(spec.special_tiles));
+    let merge_rules = MergeRules::ALL
+        .iter()
+        .position(|rules| *rules == spec.merge_rules)
+        .unwrap_or(0);
+    bytes.push(merge_rules as u8);
+    bytes.push(u8::from(spec.wraparound));
+    bytes.push(checksum(&bytes));
+
+    let mut code = String::new();
+    for (index, character) in to_base32(&bytes).chars().enumerate() {
+        if index > 0 && index % GROUP_LENGTH == 0 {
+            code.push('-');
+        }
+        code.push(character);
+    }
+    code
+}
+
+/// Reads a code made by [`encode`]. Case, dashes and spaces don't matter,
+/// and the letters O, I and L are read as the digits they look like.
+pub fn decode(code: &str) -> Result<GameSpec, CodeError> {
+    let bytes = from_base32(code)?;
+    let (&sum, bytes) = bytes.split_last().ok_or(CodeError::Checksum)?;
+    if checksum(bytes) != sum {
+        return Err(CodeError::Checksum);
+    }
+    let mut reader = Reader(bytes);
+    let version = reader.byte()?;
+    if version != CODE_VERSION {
+        return Err(CodeError::UnknownVersion(version));
+    }
+    let board_size = reader.byte()?;
+    if !BOARD_SIZES.contains(&board_size) {
+        return Err(CodeError::Invalid);
+    }
+    let mode = *MODES
+        .get(usize::from(reader.byte()?))
+        .ok_or(CodeError::Invalid)?;
+    if mode == GameMode::Daily {
+        return Err(CodeError::Invalid);
+    }
+    let four_chance = f32::from(reader.byte()?.min(100)) / 100.0;
+    let puzzle = match reader.byte()? {
+        0 => None,
+        kind => {
+            let moves = reader.u32()?;
+            let target = reader.u32()?;
+            let goal = match kind {
+                1 => PuzzleGoal::Tile(target),
+                2 => PuzzleGoal::Score(target),
+                _ => return Err(CodeError::Invalid),
+            };
+            Some(Puzzle { moves, goal })
+        }
+    };
+    let spawns = match reader.byte()? {
+        0 => Spawns::Random,
+        1 => Spawns::Seed(reader.varint()?),
+        2 => Spawns::Sequence(reader.tiles(board_size)?),
+        _ => return Err(CodeError::Invalid),
+    };
+    let tiles = reader.tiles(board_size)?;
+    let score = reader.u32()?;
+    let mut blockers = Vec::new();
+    for _ in 0..reader.varint()? {
+        blockers.push(reader.cell(board_size)?);
+    }
+    let special_tiles = reader.byte()? != 0;
+    let merge_rules = *MergeRules::ALL
+        .get(usize::from(reader.byte()?))
+        .ok_or(CodeError::Invalid)?;
+    let wraparound = reader.byte()? != 0;
+    if !reader.0.is_empty() {
+        return Err(CodeError::Invalid);
+    }
+    // Every tile and blocker needs a cell to itself
+    let mut cells = HashSet::new();
+    let overlapping = tiles
+        .iter()
+        .map(|(position, _)| position)
+        .chain(&blockers)
+        .any(|position| !cells.insert(*position));
+    if overlapping {
+        return Err(CodeError::Invalid);
+    }
+    Ok(GameSpec {
+        board_size,
+        mode,
+        puzzle,
+        four_chance,
+        tiles,
+        spawns,
+        blockers,
+        special_tiles,
+        merge_rules,
+        wraparound,
+        score,
+    })
+}
+
+/// The game asked for with `--challenge <code>` on the command line, if any.
+pub fn challenge_arg() -> Option<GameSpec> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let code = match arg.strip_prefix("--challenge") {
+            Some("") => args.next()?,
+            Some(code) if code.starts_with('=') => code[1..].to_string(),
+            _ => continue,
+        };
+        return match decode(&code) {
+            Ok(spec) => Some(spec),
+            Err(error) => {
+                eprintln!("ignoring challenge code {}: {}", code, error);
+                None
+            }
+        };
+    }
+    None
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.rotate_left(3) ^ byte)
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Tiles as a count followed by each one's cell, numbered across the rows,
+/// and value. Blockers are written the same way, without the values.
+fn push_tiles(bytes: &mut Vec<u8>, board_size: u8, tiles: &[(Position, u32)]) {
+    push_varint(bytes, tiles.len() as u64);
+    for (position, value) in tiles {
+        bytes.push(position.y * board_size + position.x);
+        push_varint(bytes, u64::from(*value));
+    }
+}
+
+fn to_base32(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = buffer << 8 | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        text.push(ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    text
+}
+
+fn from_base32(text: &str) -> Result<Vec<u8>, CodeError> {
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for character in text.chars() {
+        let digit = match character.to_ascii_uppercase() {
+            '-' | ' ' => continue,
+            'O' => 0,
+            'I' | 'L' => 1,
+            other => ALPHABET
+                .iter()
+                .position(|letter| *letter as char == other)
+                .ok_or(CodeError::InvalidCharacter(character))? as u32,
+        };
+        buffer = buffer << 5 | digit;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, CodeError> {
+        let (&byte, rest) = self.0.split_first().ok_or(CodeError::Invalid)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, CodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(CodeError::Invalid)
+    }
+
+    fn u32(&mut self) -> Result<u32, CodeError> {
+        u32::try_from(self.varint()?).map_err(|_| CodeError::Invalid)
+    }
+
+    fn tiles(&mut self, board_size: u8) -> Result<Vec<(Position, u32)>, CodeError> {
+        let count = self.varint()?;
+        let mut tiles = Vec::new();
+        for _ in 0..count {
+            let position = self.cell(board_size)?;
+            // Values past the biggest tile would overflow once merged
+            let value = self.u32()?;
+            if value == 0 || value > MAX_VALUE {
+                return Err(CodeError::Invalid);
+            }
+            tiles.push((position, value));
+        }
+        Ok(tiles)
+    }
+
+    fn cell(&mut self, board_size: u8) -> Result<Position, CodeError> {
+        let cell = self.byte()?;
+        if cell >= board_size * board_size {
+            return Err(CodeError::Invalid);
+        }
+        Ok(Position {
+            x: cell % board_size,
+            y: cell / board_size,
+        })
+    }
+}
+
+/// A code being typed in on the enter code screen.
+#[derive(Resource, Default)]
+pub struct CodeEntry {
+    pub code: String,
+    /// Why the last code tried couldn't be played.
+    pub error: Option<CodeError>,
+    /// What happened the last time a code was copied or pasted.
+    pub status: String,
+}
+
+impl CodeEntry {
+    /// Replaces the code with the one on the clipboard, leaving out
+    /// anything that can't be part of a code.
+    fn paste(&mut self) {
+        match clipboard::paste() {
+            Ok(text) => {
+                self.code = text
+                    .chars()
+                    .filter(|character| character.is_ascii_alphanumeric() || *character == '-')
+                    .map(|character| character.to_ascii_uppercase())
+                    .take(CODE_LENGTH)
+                    .collect();
+                self.error = None;
+                self.status.clear();
+            }
+            Err(error) => self.status = format!("Couldn't paste: {}", error),
+        }
+    }
+}
+
+pub struct ChallengePlugin;
+
+impl Plugin for ChallengePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CodeEntry>()
+            .add_system(handle_code_actions)
+            .add_system_set(
+                SystemSet::on_update(RunState::EnterCode)
+                    .with_system(type_code)
+            );
+    }
+}
+
+/// Copies the code for the game in progress, or pastes one in to play.
+fn handle_code_actions(
+    mut button_reader: EventReader<ButtonActionEvent>,
+    game: Res<Game>,
+    mut code_entry: ResMut<CodeEntry>,
+) {
+    for ButtonActionEvent(action) in button_reader.iter() {
+        match action {
+            ButtonAction::CopyCode => {
+                let code = game.spec.as_ref().map(encode).unwrap_or_default();
+                code_entry.status = match clipboard::copy(&code) {
+                    Ok(()) => "Copied to the clipboard".to_string(),
+                    Err(error) => format!("Couldn't copy: {}", error),
+                };
+            }
+            ButtonAction::PasteCode => code_entry.paste(),
+            _ => {}
+        }
+    }
+}
+
+fn type_code(
+    mut char_reader: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut code_entry: ResMut<CodeEntry>,
+) {
+    let shortcut = keyboard_input.any_pressed([
+        KeyCode::LControl,
+        KeyCode::RControl,
+        KeyCode::LWin,
+        KeyCode::RWin,
+    ]);
+    if shortcut {
+        char_reader.clear();
+        if keyboard_input.just_pressed(KeyCode::V) {
+            code_entry.paste();
+        }
+        return;
+    }
+    for ReceivedCharacter { char: character, .. } in char_reader.iter() {
+        if (character.is_ascii_alphanumeric() || *character == '-')
+            && code_entry.code.len() < CODE_LENGTH
+        {
+            code_entry.code.push(character.to_ascii_uppercase());
+            code_entry.error = None;
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        code_entry.code.pop();
+        code_entry.error = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> GameSpec {
+        GameSpec {
+            board_size: 5,
+            mode: GameMode::Puzzle,
+            puzzle: Some(Puzzle { moves: 40, goal: PuzzleGoal::Tile(256) }),
+            four_chance: 0.2,
+            tiles: vec![(Position { x: 0, y: 0 }, 2), (Position { x: 4, y: 3 }, 128)],
+            spawns: Spawns::Seed(1_234_567),
+            blockers: vec![Position { x: 2, y: 2 }],
+            special_tiles: true,
+            merge_rules: MergeRules::Fibonacci,
+            wraparound: true,
+            score: 300,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(decode(&encode(&spec())), Ok(spec()));
+    }
+
+    #[test]
+    fn round_trip_sequence() {
+        let spec = GameSpec {
+            spawns: Spawns::Sequence(vec![(Position { x: 1, y: 1 }, 4)]),
+            puzzle: None,
+            mode: GameMode::Classic,
+            ..spec()
+        };
+        assert_eq!(decode(&encode(&spec)), Ok(spec));
+    }
+
+    #[test]
+    fn reads_lowercase_and_lookalikes() {
+        let code = encode(&spec());
+        let typed = code.to_lowercase().replace('0', "o").replace('1', "l");
+        assert_eq!(decode(&typed), Ok(spec()));
+    }
+
+    #[test]
+    fn rejects_typos() {
+        let mut typo: Vec<char> = encode(&spec()).chars().collect();
+        typo[1] = if typo[1] == 'A' { 'B' } else { 'A' };
+        let typo: String = typo.into_iter().collect();
+        assert_eq!(decode(&typo), Err(CodeError::Checksum));
+        assert_eq!(decode("U"), Err(CodeError::InvalidCharacter('U')));
+    }
+
+    #[test]
+    fn rejects_overlapping_cells() {
+        let stacked = GameSpec {
+            tiles: vec![(Position { x: 1, y: 1 }, 2), (Position { x: 1, y: 1 }, 4)],
+            ..spec()
+        };
+        assert_eq!(decode(&encode(&stacked)), Err(CodeError::Invalid));
+        let on_blocker = GameSpec {
+            tiles: vec![(Position { x: 2, y: 2 }, 2)],
+            ..spec()
+        };
+        assert_eq!(decode(&encode(&on_blocker)), Err(CodeError::Invalid));
+    }
+
+    #[test]
+    fn shares_daily_games_as_classic() {
+        let daily = GameSpec { mode: GameMode::Daily, puzzle: None, ..spec() };
+        let decoded = decode(&encode(&daily)).unwrap();
+        assert_eq!(decoded.mode, GameMode::Classic);
+    }
+
+    #[test]
+    fn rejects_daily_mode() {
+        let daily = MODES.iter().position(|mode| *mode == GameMode::Daily).unwrap() as u8;
+        let mut bytes = vec![CODE_VERSION, 4, daily, 10, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.push(checksum(&bytes));
+        assert_eq!(decode(&to_base32(&bytes)), Err(CodeError::Invalid));
+    }
+
+    #[test]
+    fn rejects_impossible_values() {
+        for value in [0, MAX_VALUE + 1, u32::MAX] {
+            let tile = GameSpec {
+                tiles: vec![(Position { x: 1, y: 1 }, value)],
+                ..spec()
+            };
+            assert_eq!(decode(&encode(&tile)), Err(CodeError::Invalid));
+            let spawned = GameSpec {
+                spawns: Spawns::Sequence(vec![(Position { x: 1, y: 1 }, value)]),
+                ..spec()
+            };
+            assert_eq!(decode(&encode(&spawned)), Err(CodeError::Invalid));
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use arboard::Clipboard;
+use std::sync::Mutex;
+
+/// The system clipboard, opened the first time it's needed. It's kept open
+/// for the rest of the run, since on some systems text copied from it
+/// disappears once it's closed.
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+fn with_clipboard<T>(
+    action: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+) -> Result<T, String> {
+    let mut clipboard = CLIPBOARD.lock().map_err(|error| error.to_string())?;
+    if clipboard.is_none() {
+        *clipboard = Some(Clipboard::new().map_err(|error| error.to_string())?);
+    }
+    let clipboard = clipboard.as_mut().expect("clipboard to be open");
+    action(clipboard).map_err(|error| error.to_string())
+}
+
+/// Puts `text` on the clipboard.
+pub fn copy(text: &str) -> Result<(), String> {
+    with_clipboard(|clipboard| clipboard.set_text(text))
+}
+
+/// The text on the clipboard.
+pub fn paste() -> Result<String, String> {
+    with_clipboard(|clipboard| clipboard.get_text())
+}
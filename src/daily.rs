@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{
+    date::Date, game_reset, storage, Game, GameFinishedEvent, GameMode, GameRecord, GameSpec,
+    MergeRules, RunState, Spawns,
+};
+
+const DAILY_FILE: &str = "daily.ron";
+
+const DAILY_BOARD_SIZE: u8 = 4;
+
+/// The scored attempt at one day's challenge. It's taken as soon as the game
+/// starts, and stays at zero if the game is left before a move is made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub date: Date,
+    pub score: u32,
+    pub max_tile: u32,
+    pub moves: u32,
+    /// Seconds of play.
+    pub duration: f32,
+}
+
+/// Past daily challenge results, oldest first.
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyHistory {
+    results: Vec<DailyResult>,
+}
+
+impl DailyHistory {
+    pub fn load() -> Self {
+        storage::load(DAILY_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(DAILY_FILE, self);
+    }
+
+    pub fn result(&self, date: Date) -> Option<&DailyResult> {
+        self.results
+            .binary_search_by_key(&date, |result| result.date)
+            .ok()
+            .map(|index| &self.results[index])
+    }
+}
+
+/// The challenge for `date`. Days follow the player's local calendar, so
+/// everyone gets the same board on the same date wherever they are.
+pub fn daily_spec(date: Date) -> GameSpec {
+    GameSpec {
+        board_size: DAILY_BOARD_SIZE,
+        mode: GameMode::Daily,
+        puzzle: None,
+        four_chance: 0.1,
+        tiles: Vec::new(),
+        spawns: Spawns::Seed(date.days_since_epoch() as u64),
+        blockers: Vec::new(),
+        special_tiles: false,
+        merge_rules: MergeRules::Classic,
+        wraparound: false,
+        score: 0,
+    }
+}
+
+/// The day a daily game's challenge was for.
+pub fn challenge_date(spec: &GameSpec) -> Option<Date> {
+    match spec.spawns {
+        Spawns::Seed(seed) if spec.mode == GameMode::Daily => Some(Date::from_days(seed as i64)),
+        _ => None,
+    }
+}
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(DailyHistory::load())
+            .init_resource::<DailyAttempt>()
+            .add_system(record_daily_results)
+            .add_system_set(
+                SystemSet::on_enter(RunState::Playing)
+                    .with_system(start_daily_attempt.after(game_reset))
+            );
+    }
+}
+
+/// The day whose scored attempt is being played, if the game in progress
+/// is one.
+#[derive(Resource, Default)]
+struct DailyAttempt(Option<Date>);
+
+/// Uses up the day's attempt as soon as its first game starts, so leaving
+/// it and starting over doesn't get another go. Replays of a day already
+/// attempted don't count.
+fn start_daily_attempt(
+    mut finished_reader: EventReader<GameFinishedEvent>,
+    game: Res<Game>,
+    mut history: ResMut<DailyHistory>,
+    mut attempt: ResMut<DailyAttempt>,
+) {
+    // A game restarted straight away finishes in the same frame, before
+    // `record_daily_results` has had a look
+    for GameFinishedEvent(record) in finished_reader.iter() {
+        fill_in_attempt(record, &mut history, &mut attempt);
+    }
+    attempt.0 = None;
+    let Some(date) = game.spec.as_ref().and_then(challenge_date) else {
+        return;
+    };
+    if history.result(date).is_some() {
+        return;
+    }
+    let index = history.results.partition_point(|result| result.date < date);
+    history.results.insert(index, DailyResult {
+        date,
+        score: 0,
+        max_tile: 0,
+        moves: 0,
+        duration: 0.0,
+    });
+    history.save();
+    attempt.0 = Some(date);
+}
+
+fn record_daily_results(
+    mut finished_reader: EventReader<GameFinishedEvent>,
+    mut history: ResMut<DailyHistory>,
+    mut attempt: ResMut<DailyAttempt>,
+) {
+    for GameFinishedEvent(record) in finished_reader.iter() {
+        fill_in_attempt(record, &mut history, &mut attempt);
+    }
+}
+
+/// Fills in the day's attempt once its game is over.
+fn fill_in_attempt(record: &GameRecord, history: &mut DailyHistory, attempt: &mut DailyAttempt) {
+    let Some(date) = record.spec.as_ref().and_then(challenge_date) else {
+        return;
+    };
+    if attempt.0 != Some(date) {
+        return;
+    }
+    attempt.0 = None;
+    let Ok(index) = history.results.binary_search_by_key(&date, |result| result.date) else {
+        return;
+    };
+    history.results[index] = DailyResult {
+        date,
+        score: record.score,
+        max_tile: record.max_tile,
+        moves: record.moves,
+        duration: record.duration,
+    };
+    history.save();
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use crate::{
+    render_tile_points, resize_board, spawn_blocker, spawn_tile, step_through, Blocker, Board,
+    ButtonAction, ButtonActionEvent, FontSpec, Game, GameMode, GameSpec, MergeRules, Points,
+    Position, RunState, Settings, Spawns, TilePlaceholder, BOARD_SIZES,
+};
+
+/// Biggest value a tile can be raised to in the editor.
+pub const MAX_VALUE: u32 = 1 << 30;
+
+/// The board being laid out in the editor, apart from its tiles.
+#[derive(Resource, Default)]
+pub struct Sandbox {
+    pub board_size: u8,
+    /// Score loaded along with a position, carried into the game played
+    /// from it.
+    pub score: u32,
+}
+
+impl Sandbox {
+    /// A practice game starting from `tiles`. An empty board starts with two
+    /// random tiles as usual.
+    pub fn spec(
+        &self,
+        tiles: Vec<(Position, u32)>,
+        blockers: Vec<Position>,
+        four_chance: f32,
+    ) -> GameSpec {
+        GameSpec {
+            board_size: self.board_size,
+            mode: GameMode::Practice,
+            puzzle: None,
+            four_chance,
+            tiles,
+            spawns: Spawns::Seed(u64::from(random::<u32>())),
+            blockers,
+            special_tiles: false,
+            merge_rules: MergeRules::Classic,
+            wraparound: false,
+            score: self.score,
+        }
+    }
+}
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Sandbox>()
+            .add_system_set(
+                SystemSet::on_enter(RunState::Editor)
+                    .with_system(open_editor)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Editor)
+                    .with_system(handle_editor_actions)
+                    .with_system(apply_sandbox_size.after(handle_editor_actions))
+                    .with_system(edit_tiles)
+                    .with_system(render_tile_points.after(edit_tiles))
+            );
+    }
+}
+
+/// Starts the editor from an empty board of the size in the settings.
+fn open_editor(
+    mut commands: Commands,
+    tiles: Query<Entity, With<Position>>,
+    mut game: ResMut<Game>,
+    mut sandbox: ResMut<Sandbox>,
+    settings: Res<Settings>,
+) {
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *game = Game {
+        score_best: game.score_best,
+        ..default()
+    };
+    sandbox.board_size = settings.board_size;
+    sandbox.score = 0;
+}
+
+fn handle_editor_actions(
+    mut commands: Commands,
+    mut button_reader: EventReader<ButtonActionEvent>,
+    tiles: Query<Entity, With<Position>>,
+    mut sandbox: ResMut<Sandbox>,
+) {
+    for ButtonActionEvent(action) in button_reader.iter() {
+        match action {
+            ButtonAction::SandboxSize(step) => {
+                sandbox.board_size = step_through(&BOARD_SIZES, sandbox.board_size, *step);
+            }
+            ButtonAction::ClearBoard => {
+                for entity in tiles.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                sandbox.score = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn apply_sandbox_size(
+    mut commands: Commands,
+    sandbox: Res<Sandbox>,
+    tiles: Query<(Entity, &Position)>,
+    mut boards: Query<(Entity, &mut Board)>,
+    placeholders: Query<Entity, With<TilePlaceholder>>,
+) {
+    if !sandbox.is_changed() {
+        return;
+    }
+    for (entity, position) in tiles.iter() {
+        if position.x >= sandbox.board_size || position.y >= sandbox.board_size {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    resize_board(&mut commands, &mut boards, &placeholders, sandbox.board_size);
+}
+
+/// Left-clicking a cell places a 2 or doubles the tile there, right-clicking
+/// halves the tile or removes it once it's down to 2. Middle-clicking puts a
+/// blocker in the cell or takes it away.
+#[allow(clippy::too_many_arguments)]
+fn edit_tiles(
+    mut commands: Commands,
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    cameras: Query<&Transform, With<Camera2d>>,
+    interactions: Query<&Interaction>,
+    query_board: Query<&Board>,
+    font_spec: Res<FontSpec>,
+    mut tiles: Query<(Entity, &Position, &mut Points)>,
+    blockers: Query<(Entity, &Position), With<Blocker>>,
+) {
+    let raise = mouse_buttons.just_pressed(MouseButton::Left);
+    let lower = mouse_buttons.just_pressed(MouseButton::Right);
+    let block = mouse_buttons.just_pressed(MouseButton::Middle);
+    if !raise && !lower && !block {
+        return;
+    }
+    // Clicks on the editor's buttons aren't meant for the board
+    if interactions.iter().any(|interaction| *interaction != Interaction::None) {
+        return;
+    }
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let camera = cameras.single();
+    let point = cursor - Vec2::new(window.width(), window.height()) / 2.0
+        + camera.translation.truncate();
+    let board = query_board.single();
+    let Some(cell) = board.cell_at(point) else {
+        return;
+    };
+
+    let blocker = blockers
+        .iter()
+        .find(|(_, position)| **position == cell)
+        .map(|(entity, _)| entity);
+    if block {
+        match blocker {
+            Some(entity) => commands.entity(entity).despawn_recursive(),
+            None => {
+                let tile = tiles.iter().find(|(_, position, _)| **position == cell);
+                if let Some((entity, _, _)) = tile {
+                    commands.entity(entity).despawn_recursive();
+                }
+                spawn_blocker(&mut commands, board, cell);
+            }
+        }
+        return;
+    }
+    if blocker.is_some() {
+        return;
+    }
+
+    match tiles.iter_mut().find(|(_, position, _)| **position == cell) {
+        Some((_, _, mut points)) if raise => {
+            points.value = points.value.saturating_mul(2).min(MAX_VALUE);
+        }
+        None if raise => {
+            spawn_tile(&mut commands, board, &font_spec, cell, 2, None);
+        }
+        Some((entity, _, mut points)) => {
+            if points.value > 2 {
+                points.value /= 2;
+            } else {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        None => {}
+    }
+}
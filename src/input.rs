@@ -0,0 +1,448 @@
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use serde::{Deserialize, Serialize};
+use crate::{storage, BoardShift, MenuInput, MenuInputEvent, NameEntry, RunState};
+
+const BINDINGS_FILE: &str = "bindings.ron";
+
+/// Everything the player can ask the game to do, independently of which
+/// key or button they used to ask for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    ShiftLeft,
+    ShiftRight,
+    ShiftUp,
+    ShiftDown,
+    Undo,
+    Restart,
+    Hint,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::ShiftLeft,
+        Action::ShiftRight,
+        Action::ShiftUp,
+        Action::ShiftDown,
+        Action::Undo,
+        Action::Restart,
+        Action::Hint,
+    ];
+
+    pub fn board_shift(&self) -> Option<BoardShift> {
+        match self {
+            Action::ShiftLeft => Some(BoardShift::Left),
+            Action::ShiftRight => Some(BoardShift::Right),
+            Action::ShiftUp => Some(BoardShift::Up),
+            Action::ShiftDown => Some(BoardShift::Down),
+            Action::Undo | Action::Restart | Action::Hint => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::ShiftLeft => "Left",
+            Action::ShiftRight => "Right",
+            Action::ShiftUp => "Up",
+            Action::ShiftDown => "Down",
+            Action::Undo => "Undo",
+            Action::Restart => "Restart",
+            Action::Hint => "Hint",
+        }
+    }
+}
+
+/// Sent whenever the player triggers an action, whatever the input device.
+pub struct ActionEvent(pub Action);
+
+/// Keys that can be bound to actions. Escape is left out on purpose as it
+/// cancels rebinding.
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F,
+    KeyCode::G, KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L,
+    KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X,
+    KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+    KeyCode::Space, KeyCode::Return, KeyCode::Back, KeyCode::Tab,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon,
+    KeyCode::Minus, KeyCode::Equals,
+];
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|key| key_name(*key) == name)
+}
+
+/// A key was pressed for an action while already bound to another one.
+#[derive(Debug, Clone, Copy)]
+pub enum BindingError {
+    Conflict { key: KeyCode, action: Action },
+    Unbindable(KeyCode),
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BindingError::Conflict { key, action } => write!(
+                f,
+                "{} is already bound to {}",
+                key_name(*key),
+                action.label(),
+            ),
+            BindingError::Unbindable(key) => {
+                write!(f, "{} can't be bound", key_name(*key))
+            }
+        }
+    }
+}
+
+/// Which keys trigger which actions. A key maps to at most one action, an
+/// action can have any number of keys.
+#[derive(Resource, Clone, PartialEq)]
+pub struct KeyBindings {
+    keys: Vec<(KeyCode, Action)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            keys: vec![
+                (KeyCode::Left, Action::ShiftLeft),
+                (KeyCode::Right, Action::ShiftRight),
+                (KeyCode::Up, Action::ShiftUp),
+                (KeyCode::Down, Action::ShiftDown),
+                (KeyCode::A, Action::ShiftLeft),
+                (KeyCode::D, Action::ShiftRight),
+                (KeyCode::W, Action::ShiftUp),
+                (KeyCode::S, Action::ShiftDown),
+                (KeyCode::H, Action::ShiftLeft),
+                (KeyCode::L, Action::ShiftRight),
+                (KeyCode::K, Action::ShiftUp),
+                (KeyCode::J, Action::ShiftDown),
+                (KeyCode::U, Action::Undo),
+                (KeyCode::R, Action::Restart),
+                (KeyCode::T, Action::Hint),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(bound_key, _)| *bound_key == key)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = KeyCode> + '_ {
+        self.keys
+            .iter()
+            .filter(move |(_, bound_action)| *bound_action == action)
+            .map(|(key, _)| *key)
+    }
+
+    /// Adds `key` to `action`, refusing keys that already do something else.
+    pub fn bind(&mut self, key: KeyCode, action: Action) -> Result<(), BindingError> {
+        if !BINDABLE_KEYS.contains(&key) {
+            return Err(BindingError::Unbindable(key));
+        }
+        match self.action(key) {
+            Some(bound) if bound == action => Ok(()),
+            Some(bound) => Err(BindingError::Conflict { key, action: bound }),
+            None => {
+                self.keys.push((key, action));
+                Ok(())
+            }
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.keys.retain(|(_, bound_action)| *bound_action != action);
+    }
+
+    /// Loads the bindings saved by the player, falling back to the defaults.
+    pub fn load() -> Self {
+        let Some(saved) = storage::load::<Vec<(String, Action)>>(BINDINGS_FILE) else {
+            return KeyBindings::default();
+        };
+        let mut bindings = KeyBindings { keys: Vec::new() };
+        for (name, action) in saved {
+            match key_from_name(&name) {
+                Some(key) => {
+                    if let Err(error) = bindings.bind(key, action) {
+                        warn!("skipping saved binding: {}", error);
+                    }
+                }
+                None => warn!("skipping saved binding for unknown key {}", name),
+            }
+        }
+        bindings
+    }
+
+    pub fn save(&self) {
+        let saved: Vec<(String, Action)> = self
+            .keys
+            .iter()
+            .map(|(key, action)| (key_name(*key), *action))
+            .collect();
+        storage::save(BINDINGS_FILE, &saved);
+    }
+}
+
+/// How far the left stick has to be pushed to count as a move, and how far
+/// back towards the centre it has to come before it can trigger another.
+#[derive(Resource)]
+pub struct StickSettings {
+    pub deadzone: f32,
+    pub release: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        StickSettings {
+            deadzone: 0.6,
+            release: 0.3,
+        }
+    }
+}
+
+/// How far, in logical pixels, a mouse drag or touch swipe has to travel
+/// across the window before it counts as a move. Kept in step with the
+/// swipe distance setting.
+#[derive(Resource)]
+pub struct GestureSettings {
+    pub min_distance: f32,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        GestureSettings { min_distance: 50.0 }
+    }
+}
+
+impl From<BoardShift> for Action {
+    fn from(board_shift: BoardShift) -> Self {
+        match board_shift {
+            BoardShift::Left => Action::ShiftLeft,
+            BoardShift::Right => Action::ShiftRight,
+            BoardShift::Up => Action::ShiftUp,
+            BoardShift::Down => Action::ShiftDown,
+        }
+    }
+}
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(KeyBindings::load())
+            .init_resource::<StickSettings>()
+            .init_resource::<GestureSettings>()
+            .add_event::<ActionEvent>()
+            .add_system(keyboard_actions)
+            .add_system(gamepad_input)
+            .add_system(gesture_input);
+    }
+}
+
+fn keyboard_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    run_state: Res<State<RunState>>,
+    name_entry: Res<NameEntry>,
+    mut action_writer: EventWriter<ActionEvent>,
+) {
+    // Keys pressed on other screens are meant for those screens
+    if !matches!(run_state.current(), RunState::Playing | RunState::GameOver) {
+        return;
+    }
+    // ...and so are the letters of a name being typed in
+    if name_entry.is_pending() {
+        return;
+    }
+    for key in keyboard_input.get_just_pressed() {
+        if let Some(action) = bindings.action(*key) {
+            action_writer.send(ActionEvent(action));
+        }
+    }
+}
+
+/// Reads the left stick of `gamepad` as a single direction, only reporting
+/// it once per push so holding the stick doesn't repeat the move.
+fn stick_direction(
+    gamepad: Gamepad,
+    axes: &Axis<GamepadAxis>,
+    settings: &StickSettings,
+    held: &mut bool,
+) -> Option<BoardShift> {
+    let x = axes
+        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+        .unwrap_or(0.0);
+    let y = axes
+        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+        .unwrap_or(0.0);
+    let push = x.abs().max(y.abs());
+
+    if *held {
+        if push < settings.release {
+            *held = false;
+        }
+        return None;
+    }
+    if push < settings.deadzone {
+        return None;
+    }
+    *held = true;
+    Some(if x.abs() > y.abs() {
+        if x > 0.0 { BoardShift::Right } else { BoardShift::Left }
+    } else if y > 0.0 {
+        BoardShift::Up
+    } else {
+        BoardShift::Down
+    })
+}
+
+/// Plays the board with the d-pad or left stick and drives menus with the
+/// face buttons: South confirms, East goes back, West undoes, North restarts,
+/// Select asks for a hint and Start pauses.
+#[allow(clippy::too_many_arguments)]
+fn gamepad_input(
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    settings: Res<StickSettings>,
+    run_state: Res<State<RunState>>,
+    mut sticks_held: Local<HashMap<Gamepad, bool>>,
+    mut action_writer: EventWriter<ActionEvent>,
+    mut menu_writer: EventWriter<MenuInputEvent>,
+) {
+    let in_game = matches!(run_state.current(), RunState::Playing | RunState::GameOver);
+
+    for gamepad in gamepads.iter() {
+        let pressed = |button_type| {
+            buttons.just_pressed(GamepadButton::new(gamepad, button_type))
+        };
+
+        let mut directions: Vec<BoardShift> = [
+            (GamepadButtonType::DPadLeft, BoardShift::Left),
+            (GamepadButtonType::DPadRight, BoardShift::Right),
+            (GamepadButtonType::DPadUp, BoardShift::Up),
+            (GamepadButtonType::DPadDown, BoardShift::Down),
+        ]
+        .into_iter()
+        .filter(|(button_type, _)| pressed(*button_type))
+        .map(|(_, board_shift)| board_shift)
+        .collect();
+        let held = sticks_held.entry(gamepad).or_insert(false);
+        directions.extend(stick_direction(gamepad, &axes, &settings, held));
+
+        for board_shift in directions {
+            if *run_state.current() == RunState::Playing {
+                action_writer.send(ActionEvent(board_shift.into()));
+            } else {
+                menu_writer.send(MenuInputEvent(match board_shift {
+                    BoardShift::Left | BoardShift::Up => MenuInput::Previous,
+                    BoardShift::Right | BoardShift::Down => MenuInput::Next,
+                }));
+            }
+        }
+
+        if in_game {
+            if pressed(GamepadButtonType::West) {
+                action_writer.send(ActionEvent(Action::Undo));
+            }
+            if pressed(GamepadButtonType::North) {
+                action_writer.send(ActionEvent(Action::Restart));
+            }
+            if pressed(GamepadButtonType::Select) {
+                action_writer.send(ActionEvent(Action::Hint));
+            }
+        }
+        if pressed(GamepadButtonType::Start) {
+            menu_writer.send(MenuInputEvent(MenuInput::Menu));
+        }
+        if *run_state.current() != RunState::Playing {
+            if pressed(GamepadButtonType::South) {
+                menu_writer.send(MenuInputEvent(MenuInput::Activate));
+            }
+            if pressed(GamepadButtonType::East) {
+                menu_writer.send(MenuInputEvent(MenuInput::Back));
+            }
+        }
+    }
+}
+
+/// Recognizes a drag as a move along whichever axis it travelled furthest,
+/// once it covers at least `min_distance`. `delta` has y pointing up.
+fn swipe_direction(delta: Vec2, min_distance: f32) -> Option<BoardShift> {
+    if delta.length() < min_distance {
+        return None;
+    }
+    Some(if delta.x.abs() > delta.y.abs() {
+        if delta.x > 0.0 { BoardShift::Right } else { BoardShift::Left }
+    } else if delta.y > 0.0 {
+        BoardShift::Up
+    } else {
+        BoardShift::Down
+    })
+}
+
+/// Plays the board by click-dragging with the left mouse button or swiping
+/// on a touch screen. Each drag makes at most one move, as soon as it's
+/// long enough, so the player doesn't have to let go to see it happen.
+#[allow(clippy::too_many_arguments)]
+fn gesture_input(
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    touches: Res<Touches>,
+    settings: Res<GestureSettings>,
+    run_state: Res<State<RunState>>,
+    mut drag_start: Local<Option<Vec2>>,
+    mut swiped_touches: Local<HashSet<u64>>,
+    mut action_writer: EventWriter<ActionEvent>,
+) {
+    for touch in touches.iter_just_released() {
+        swiped_touches.remove(&touch.id());
+    }
+    if *run_state.current() != RunState::Playing {
+        *drag_start = None;
+        return;
+    }
+
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        *drag_start = cursor;
+    } else if !mouse_buttons.pressed(MouseButton::Left) {
+        *drag_start = None;
+    }
+    if let (Some(start), Some(cursor)) = (*drag_start, cursor) {
+        if let Some(board_shift) = swipe_direction(cursor - start, settings.min_distance) {
+            action_writer.send(ActionEvent(board_shift.into()));
+            *drag_start = None;
+        }
+    }
+
+    for touch in touches.iter() {
+        if swiped_touches.contains(&touch.id()) {
+            continue;
+        }
+        // Touch positions grow downwards, unlike the cursor position
+        let delta = (touch.position() - touch.start_position()) * Vec2::new(1.0, -1.0);
+        if let Some(board_shift) = swipe_direction(delta, settings.min_distance) {
+            action_writer.send(ActionEvent(board_shift.into()));
+            swiped_touches.insert(touch.id());
+        }
+    }
+}
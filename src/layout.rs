@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+use crate::{Board, Position, SpecialBadge, TilePlaceholder, TileText, HudRoot};
+
+/// Window size the HUD's pixel sizes were designed for.
+const REFERENCE_WIDTH: f32 = 1280.0;
+const REFERENCE_HEIGHT: f32 = 720.0;
+
+/// Height the HUD takes up at the top of the window at the reference size,
+/// for side-by-side (landscape) and stacked (portrait) headers.
+const HEADER_HEIGHT_LANDSCAPE: f32 = 130.0;
+const HEADER_HEIGHT_PORTRAIT: f32 = 200.0;
+
+/// Share of the space left under the header the board may cover.
+const BOARD_FILL: f32 = 0.9;
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(fit_to_window)
+            .add_system(rescale_board.after(fit_to_window));
+    }
+}
+
+/// Scales the HUD and the board to the primary window whenever its size
+/// changes, switching the header to a stacked layout on portrait windows.
+fn fit_to_window(
+    windows: Res<Windows>,
+    mut last_size: Local<Vec2>,
+    mut ui_scale: ResMut<UiScale>,
+    mut hud: Query<&mut Style, With<HudRoot>>,
+    mut camera: Query<&mut Transform, With<Camera2d>>,
+    mut boards: Query<&mut Board>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let size = Vec2::new(window.width(), window.height());
+    if size == *last_size || size.x <= 0.0 || size.y <= 0.0 {
+        return;
+    }
+    *last_size = size;
+
+    let portrait = size.y > size.x;
+    let scale = (size.x / REFERENCE_WIDTH)
+        .min(size.y / REFERENCE_HEIGHT)
+        .clamp(0.5, 1.5);
+    ui_scale.scale = f64::from(scale);
+
+    for mut style in hud.iter_mut() {
+        if portrait {
+            style.flex_direction = FlexDirection::ColumnReverse;
+            style.align_items = AlignItems::Center;
+            style.padding = UiRect::all(Val::Px(20.0));
+        } else {
+            style.flex_direction = FlexDirection::Row;
+            style.align_items = AlignItems::FlexStart;
+            style.padding = UiRect::all(Val::Px(50.0));
+        }
+    }
+
+    let header = scale * if portrait {
+        HEADER_HEIGHT_PORTRAIT
+    } else {
+        HEADER_HEIGHT_LANDSCAPE
+    };
+
+    // Keep the board centered in the space under the header by moving the
+    // camera up rather than moving every tile down.
+    for mut transform in camera.iter_mut() {
+        transform.translation.y = header / 2.0;
+    }
+
+    let extent = size.x.min(size.y - header) * BOARD_FILL;
+    for mut board in boards.iter_mut() {
+        board.fit_to(extent);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn rescale_board(
+    mut boards: Query<(&Board, &mut Sprite), Changed<Board>>,
+    mut placeholders: Query<
+        (&TilePlaceholder, &mut Transform, &mut Sprite),
+        Without<Board>,
+    >,
+    mut tiles: Query<
+        (&Position, &mut Transform, &mut Sprite, Option<&Children>),
+        (Without<Board>, Without<TilePlaceholder>),
+    >,
+    mut texts: Query<&mut Text, With<TileText>>,
+    mut badges: Query<
+        (&mut Transform, &mut Sprite),
+        (With<SpecialBadge>, Without<Position>, Without<Board>, Without<TilePlaceholder>),
+    >,
+) {
+    let Ok((board, mut board_sprite)) = boards.get_single_mut() else {
+        return;
+    };
+    let tile_size = Vec2::new(board.tile_size, board.tile_size);
+    board_sprite.custom_size = Some(Vec2::new(
+        board.physical_size,
+        board.physical_size,
+    ));
+
+    for (TilePlaceholder(pos), mut transform, mut sprite) in placeholders.iter_mut() {
+        transform.translation.x = board.cell_position_to_physical(pos.x);
+        transform.translation.y = board.cell_position_to_physical(pos.y);
+        sprite.custom_size = Some(tile_size);
+    }
+
+    for (pos, mut transform, mut sprite, children) in tiles.iter_mut() {
+        transform.translation.x = board.cell_position_to_physical(pos.x);
+        transform.translation.y = board.cell_position_to_physical(pos.y);
+        sprite.custom_size = Some(tile_size);
+
+        // Blockers have no text
+        if let Some(entity) = children.and_then(|children| children.first()) {
+            if let Ok(mut text) = texts.get_mut(*entity) {
+                text.sections[0].style.font_size = board.tile_size;
+            }
+        }
+    }
+
+    let (badge_size, badge_offset) = board.badge_geometry();
+    for (mut transform, mut sprite) in badges.iter_mut() {
+        transform.translation = badge_offset;
+        sprite.custom_size = Some(badge_size);
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{date::Date, storage, GameFinishedEvent, GameMode, GameRecord, MergeRules, RunState};
+
+const LEADERBOARD_FILE: &str = "leaderboard.ron";
+
+/// How many scores each leaderboard keeps.
+pub const LEADERBOARD_SIZE: usize = 10;
+
+/// Longest name that can be typed in for a high score.
+const NAME_LENGTH: usize = 16;
+
+/// Name used when the player doesn't type one in.
+const DEFAULT_NAME: &str = "Player";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub score: u32,
+    pub max_tile: u32,
+    pub moves: u32,
+    /// Seconds of play.
+    pub duration: f32,
+    pub date: Date,
+    /// Whether the score was made in a mode eligible for the leaderboard.
+    #[serde(default)]
+    pub eligible: bool,
+}
+
+/// The best scores for one board size and mode, highest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LeaderboardTable {
+    board_size: u8,
+    mode: GameMode,
+    entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Leaderboards {
+    tables: Vec<LeaderboardTable>,
+    /// The name last entered, offered again for the next high score.
+    last_name: String,
+}
+
+impl Leaderboards {
+    pub fn load() -> Self {
+        storage::load(LEADERBOARD_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(LEADERBOARD_FILE, self);
+    }
+
+    pub fn entries(&self, board_size: u8, mode: GameMode) -> &[LeaderboardEntry] {
+        self.tables
+            .iter()
+            .find(|table| table.board_size == board_size && table.mode == mode)
+            .map(|table| table.entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether `score` would make it onto the leaderboard.
+    pub fn qualifies(&self, board_size: u8, mode: GameMode, score: u32) -> bool {
+        let entries = self.entries(board_size, mode);
+        score > 0
+            && (entries.len() < LEADERBOARD_SIZE
+                || entries.last().is_none_or(|last| score > last.score))
+    }
+
+    fn insert(&mut self, board_size: u8, mode: GameMode, entry: LeaderboardEntry) {
+        let index = match self
+            .tables
+            .iter()
+            .position(|table| table.board_size == board_size && table.mode == mode)
+        {
+            Some(index) => index,
+            None => {
+                self.tables.push(LeaderboardTable {
+                    board_size,
+                    mode,
+                    entries: Vec::new(),
+                });
+                self.tables.len() - 1
+            }
+        };
+        let entries = &mut self.tables[index].entries;
+        // Ties go below the scores that got there first
+        let rank = entries.partition_point(|other| other.score >= entry.score);
+        entries.insert(rank, entry);
+        entries.truncate(LEADERBOARD_SIZE);
+    }
+
+    fn name_or_default(&self) -> String {
+        if self.last_name.is_empty() {
+            DEFAULT_NAME.to_string()
+        } else {
+            self.last_name.clone()
+        }
+    }
+}
+
+/// A high score waiting for the player to type in their name on the
+/// game-over screen.
+#[derive(Resource, Default)]
+pub struct NameEntry {
+    record: Option<GameRecord>,
+    pub name: String,
+}
+
+impl NameEntry {
+    pub fn is_pending(&self) -> bool {
+        self.record.is_some()
+    }
+
+    pub fn score(&self) -> u32 {
+        self.record.as_ref().map_or(0, |record| record.score)
+    }
+
+    /// Adds the pending score to its leaderboard under the name typed in.
+    pub fn submit(&mut self, leaderboards: &mut Leaderboards) {
+        let Some(record) = self.record.take() else {
+            return;
+        };
+        let name = self.name.trim();
+        if !name.is_empty() {
+            leaderboards.last_name = name.to_string();
+        }
+        let name = leaderboards.name_or_default();
+        leaderboards.insert(record.board_size, record.mode, entry_for(&record, name));
+        leaderboards.save();
+    }
+
+    /// Leaves the pending score off the leaderboard.
+    pub fn skip(&mut self) {
+        self.record = None;
+    }
+}
+
+fn entry_for(record: &GameRecord, name: String) -> LeaderboardEntry {
+    LeaderboardEntry {
+        name,
+        score: record.score,
+        max_tile: record.max_tile,
+        moves: record.moves,
+        duration: record.duration,
+        date: Date::today(),
+        eligible: record.eligible,
+    }
+}
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Leaderboards::load())
+            .init_resource::<NameEntry>()
+            .add_system(record_high_scores)
+            .add_system(type_name)
+            .add_system_set(
+                SystemSet::on_exit(RunState::GameOver)
+                    .with_system(submit_on_leave)
+            );
+    }
+}
+
+/// Asks for a name when a game that ends on the game-over screen makes the
+/// leaderboard. Games left some other way are entered under the last name used.
+fn record_high_scores(
+    mut finished_reader: EventReader<GameFinishedEvent>,
+    run_state: Res<State<RunState>>,
+    mut leaderboards: ResMut<Leaderboards>,
+    mut name_entry: ResMut<NameEntry>,
+) {
+    for GameFinishedEvent(record) in finished_reader.iter() {
+        // Every day's board is different, so daily games go on the daily
+        // calendar instead. Blockers, special tiles, other numbers and
+        // wraparound change the game too much to compare.
+        if record.mode == GameMode::Daily
+            || record.spec.as_ref().is_some_and(|spec| {
+                !spec.blockers.is_empty()
+                    || spec.special_tiles
+                    || spec.merge_rules != MergeRules::Classic
+                    || spec.wraparound
+            })
+            || !leaderboards.qualifies(record.board_size, record.mode, record.score)
+        {
+            continue;
+        }
+        if *run_state.current() == RunState::GameOver {
+            name_entry.record = Some(record.clone());
+            name_entry.name = leaderboards.last_name.clone();
+        } else {
+            let name = leaderboards.name_or_default();
+            leaderboards.insert(record.board_size, record.mode, entry_for(record, name));
+            leaderboards.save();
+        }
+    }
+}
+
+fn type_name(
+    mut char_reader: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut name_entry: ResMut<NameEntry>,
+) {
+    if !name_entry.is_pending() {
+        char_reader.clear();
+        return;
+    }
+    for ReceivedCharacter { char: character, .. } in char_reader.iter() {
+        if !character.is_control() && name_entry.name.chars().count() < NAME_LENGTH {
+            name_entry.name.push(*character);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        name_entry.name.pop();
+    }
+}
+
+/// Starting another game without answering the prompt still keeps the score.
+fn submit_on_leave(
+    mut name_entry: ResMut<NameEntry>,
+    mut leaderboards: ResMut<Leaderboards>,
+) {
+    name_entry.submit(&mut leaderboards);
+}
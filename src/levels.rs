@@ -0,0 +1,179 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::{
+    storage, GameFinishedEvent, GameMode, GameSpec, MergeRules, Position, Puzzle, PuzzleGoal,
+    Spawns,
+};
+
+const PROGRESS_FILE: &str = "levels.ron";
+
+/// A hand-made puzzle, read from a level pack.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub board_size: u8,
+    pub tiles: Vec<(Position, u32)>,
+    pub spawns: Spawns,
+    #[serde(default)]
+    pub blockers: Vec<Position>,
+    #[serde(default)]
+    pub four_chance: f32,
+    pub goal: PuzzleGoal,
+    pub moves: u32,
+    /// The most moves a solution can take for three stars and for two.
+    /// Any solution earns at least one.
+    pub stars: (u32, u32),
+}
+
+impl Level {
+    pub fn spec(&self) -> GameSpec {
+        GameSpec {
+            board_size: self.board_size,
+            mode: GameMode::Puzzle,
+            puzzle: Some(Puzzle {
+                moves: self.moves,
+                goal: self.goal,
+            }),
+            four_chance: self.four_chance,
+            tiles: self.tiles.clone(),
+            spawns: self.spawns.clone(),
+            blockers: self.blockers.clone(),
+            special_tiles: false,
+            merge_rules: MergeRules::Classic,
+            wraparound: false,
+            score: 0,
+        }
+    }
+
+    fn stars_for(&self, moves: u32) -> u8 {
+        let (three, two) = self.stars;
+        if moves <= three {
+            3
+        } else if moves <= two {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "5b1e0c7a-2f3d-4c57-9a59-6d3f0b8e2048"]
+pub struct LevelPack {
+    pub levels: Vec<Level>,
+}
+
+#[derive(Default)]
+struct LevelPackLoader;
+
+impl AssetLoader for LevelPackLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let pack: LevelPack = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(pack));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["levels.ron"]
+    }
+}
+
+/// The puzzles shown on the level select screen.
+#[derive(Resource)]
+pub struct Levels {
+    pack: Handle<LevelPack>,
+}
+
+impl FromWorld for Levels {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world
+            .get_resource_mut::<AssetServer>()
+            .unwrap();
+        Levels {
+            pack: asset_server.load("levels/puzzles.levels.ron"),
+        }
+    }
+}
+
+impl Levels {
+    /// The levels, or `None` while they're still loading.
+    pub fn get<'a>(&self, packs: &'a Assets<LevelPack>) -> Option<&'a [Level]> {
+        packs.get(&self.pack).map(|pack| pack.levels.as_slice())
+    }
+}
+
+/// Best star rating earned on each level, by name. Levels missing from it
+/// haven't been solved yet.
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelProgress {
+    stars: BTreeMap<String, u8>,
+}
+
+impl LevelProgress {
+    pub fn load() -> Self {
+        storage::load(PROGRESS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(PROGRESS_FILE, self);
+    }
+
+    pub fn stars(&self, level: &Level) -> u8 {
+        self.stars.get(&level.name).copied().unwrap_or(0)
+    }
+}
+
+pub struct LevelsPlugin;
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<LevelPack>()
+            .init_asset_loader::<LevelPackLoader>()
+            .init_resource::<Levels>()
+            .insert_resource(LevelProgress::load())
+            .add_system(record_level_progress);
+    }
+}
+
+/// Awards stars for a solved game that was set up exactly like a level,
+/// however it was started.
+fn record_level_progress(
+    mut finished_reader: EventReader<GameFinishedEvent>,
+    levels: Res<Levels>,
+    packs: Res<Assets<LevelPack>>,
+    mut progress: ResMut<LevelProgress>,
+) {
+    for GameFinishedEvent(record) in finished_reader.iter() {
+        if !record.solved {
+            continue;
+        }
+        let Some(spec) = &record.spec else {
+            continue;
+        };
+        let Some(level) = levels
+            .get(&packs)
+            .and_then(|levels| levels.iter().find(|level| level.spec() == *spec))
+        else {
+            continue;
+        };
+        let stars = level.stars_for(record.moves);
+        if stars > progress.stars(level) {
+            progress.stars.insert(level.name.clone(), stars);
+            progress.save();
+        }
+    }
+}
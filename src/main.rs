@@ -1,29 +1,62 @@
+mod challenge;
+mod clipboard;
+mod daily;
+mod date;
+mod editor;
+mod input;
+mod layout;
+mod leaderboard;
+mod levels;
+mod notation;
+mod rules;
+mod settings;
+mod sound;
+mod stats;
+mod storage;
 mod ui;
+use input::*;
+use layout::*;
+use challenge::*;
+use daily::*;
+use editor::*;
+use leaderboard::*;
+use levels::*;
+use notation::*;
+use rules::*;
+use settings::*;
+use sound::*;
+use stats::*;
 use ui::*;
 
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
 use bevy_easings::*;
 use itertools::Itertools;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
-    convert::TryFrom, 
     cmp::Ordering, 
-    ops::Range
+    collections::VecDeque,
 };
 
 const TILE_SIZE: f32 = 40.0;
 const TILE_SPACER: f32 = 10.0;
 
 pub struct Materials {
+    pub background: Color,
     pub board: Color,
     pub tile_placeholder: Color,
     pub tile: Color,
+    pub tile_text: Color,
+    pub blocker: Color,
     pub none: Color,
 }
 pub const MATERIALS: Materials = Materials {
+    background: Color::rgb(0.4, 0.4, 0.4),
     board: Color::rgb(0.7, 0.7, 0.8),
     tile_placeholder: Color::rgb(0.75, 0.75, 0.9),
     tile: Color::rgb(0.9, 0.9, 1.0),
+    tile_text: Color::BLACK,
+    blocker: Color::rgb(0.35, 0.35, 0.45),
     none: Color::NONE,
 };
 
@@ -31,42 +64,184 @@ pub const MATERIALS: Materials = Materials {
 struct Board {
     size: u8,
     physical_size: f32,
+    tile_size: f32,
+    tile_spacer: f32,
 }
 impl Board {
     fn new(size: u8) -> Self {
-        let physical_size = f32::from(size) * TILE_SIZE 
-            + f32::from(size + 1) * TILE_SPACER;
-        Board {
+        let mut board = Board {
             size,
-            physical_size,
-        }
+            physical_size: 0.0,
+            tile_size: 0.0,
+            tile_spacer: 0.0,
+        };
+        board.set_tile_size(TILE_SIZE);
+        board
+    }
+    /// Size and offset from the tile's centre of the stripe marking a
+    /// special tile.
+    fn badge_geometry(&self) -> (Vec2, Vec3) {
+        let height = self.tile_size / 8.0;
+        (
+            Vec2::new(self.tile_size, height),
+            Vec3::new(0.0, (height - self.tile_size) / 2.0, 0.5),
+        )
+    }
+
+    /// Rescales the board, keeping the spacer proportional to the tiles.
+    fn set_tile_size(&mut self, tile_size: f32) {
+        self.tile_size = tile_size;
+        self.tile_spacer = tile_size * TILE_SPACER / TILE_SIZE;
+        self.physical_size = f32::from(self.size) * self.tile_size
+            + f32::from(self.size + 1) * self.tile_spacer;
+    }
+    /// Picks the largest tile size for which the whole board fits in `extent`.
+    fn fit_to(&mut self, extent: f32) {
+        let spacer_ratio = TILE_SPACER / TILE_SIZE;
+        let tile_size = extent
+            / (f32::from(self.size) + f32::from(self.size + 1) * spacer_ratio);
+        self.set_tile_size(tile_size.max(1.0));
     }
     fn cell_position_to_physical(&self, pos: u8) -> f32 {
-        let offset = -self.physical_size / 2.0 + 0.5 * TILE_SIZE;
-        offset + f32::from(pos) * TILE_SIZE + f32::from(pos + 1) * TILE_SPACER
+        let offset = -self.physical_size / 2.0 + 0.5 * self.tile_size;
+        offset + f32::from(pos) * self.tile_size + f32::from(pos + 1) * self.tile_spacer
+    }
+    /// The cell under `point`, relative to the board's center, if it's on one
+    /// rather than between cells or off the board.
+    fn cell_at(&self, point: Vec2) -> Option<Position> {
+        let step = self.tile_size + self.tile_spacer;
+        let cell = |coordinate: f32| {
+            let offset = coordinate + self.physical_size / 2.0 - self.tile_spacer;
+            let index = (offset / step).floor();
+            let on_cell = offset >= 0.0
+                && index < f32::from(self.size)
+                && offset - index * step < self.tile_size;
+            on_cell.then_some(index as u8)
+        };
+        Some(Position {
+            x: cell(point.x)?,
+            y: cell(point.y)?,
+        })
     }
 }
 
+/// The empty cell sprites drawn under the tiles, remembering which cell
+/// they sit in so they can be moved when the board is rescaled.
+#[derive(Component)]
+struct TilePlaceholder(Position);
+
+/// A cell no tile can move into, slide past or merge with. Blockers have a
+/// `Position` but no `Points`, so queries for tiles leave them out.
+#[derive(Component)]
+struct Blocker;
+
 #[derive(Component, PartialEq)]
 struct Points {
     value: u32,
 }
 
-#[derive(Component, PartialEq, Copy, Clone, Eq, Hash)]
-struct Position {
+/// A tile that merges with any other tile, taking on its value.
+#[derive(Component)]
+struct Wildcard;
+
+/// A tile that clears the tiles around it when it's merged.
+#[derive(Component)]
+struct Bomb;
+
+/// A tile that doubles whatever it's merged into, on top of the merge.
+#[derive(Component)]
+struct Doubler;
+
+/// The stripe along the bottom of a special tile, coloured for its kind.
+#[derive(Component)]
+struct SpecialBadge;
+
+/// Which special rule a tile follows, if any, when it's merged.
+type SpecialTile<'a> = (Option<&'a Wildcard>, Option<&'a Bomb>, Option<&'a Doubler>);
+
+/// Tiles with rules of their own, turning up now and then among the new
+/// tiles when special tiles are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Special {
+    Wildcard,
+    Bomb,
+    Doubler,
+}
+
+impl Special {
+    const ALL: [Special; 3] = [Special::Wildcard, Special::Bomb, Special::Doubler];
+
+    /// The special read off a tile's components.
+    fn of((wildcard, bomb, doubler): SpecialTile) -> Option<Special> {
+        if wildcard.is_some() {
+            Some(Special::Wildcard)
+        } else if bomb.is_some() {
+            Some(Special::Bomb)
+        } else if doubler.is_some() {
+            Some(Special::Doubler)
+        } else {
+            None
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Special::Wildcard => Color::rgb(0.55, 0.35, 0.85),
+            Special::Bomb => Color::rgb(0.85, 0.25, 0.2),
+            Special::Doubler => Color::rgb(0.95, 0.7, 0.15),
+        }
+    }
+}
+
+/// The value a run of tiles merges into under `rule`, or `None` if they
+/// can't be merged. Wildcards stand in for whatever the other tiles need,
+/// and a doubler steps the result up once more.
+fn merge_value(rule: &dyn MergeRule, tiles: &[(u32, Option<Special>)]) -> Option<u32> {
+    let (anchor, _) = tiles
+        .iter()
+        .find(|(_, special)| *special != Some(Special::Wildcard))?;
+    let values: Vec<u32> = tiles
+        .iter()
+        .map(|&(value, special)| match special {
+            Some(Special::Wildcard) => rule.partner(*anchor),
+            _ => value,
+        })
+        .collect();
+    let merged = rule.merge(&values)?;
+    if tiles.iter().any(|(_, special)| *special == Some(Special::Doubler)) {
+        Some(rule.step_up(merged))
+    } else {
+        Some(merged)
+    }
+}
+
+#[derive(Component, Debug, PartialEq, Copy, Clone, Eq, Hash, Serialize, Deserialize)]
+pub struct Position {
     x: u8,
     y: u8,
 }
 
+impl Position {
+    /// Whether `other` is the next cell over, across or down.
+    fn is_next_to(&self, other: &Position) -> bool {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y) == 1
+    }
+}
+
 #[derive(Component)]
 struct TileText;
 
+/// A tile shrinking away after being cleared off a locked up board. It has
+/// already lost its `Position`, so it no longer takes up a cell.
+#[derive(Component)]
+struct Clearing(Timer);
+
 #[derive(Resource)]
 struct FontSpec {
     family: Handle<Font>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum BoardShift {
     Left,
     Right,
@@ -108,12 +283,16 @@ impl BoardShift {
         }
     }
 
+    /// Moves `position` to column `index`, counted from the edge tiles are
+    /// shifted towards. Indices past the far edge wrap back around to the
+    /// near one, for boards whose rows are rings.
     fn set_column_position(
         &self,
         board_size: u8,
-        position: &mut Mut<Position>,
+        position: &mut Position,
         index: u8,
     ) {
+        let index = index % board_size;
         match self {
             BoardShift::Left => {position.x = index;}
             BoardShift::Right => {position.x = board_size - 1 - index}
@@ -121,6 +300,41 @@ impl BoardShift {
             BoardShift::Down => {position.y = index}
         }
     }
+    /// How far `position` is from the edge tiles are shifted towards.
+    fn column_index(
+        &self,
+        board_size: u8,
+        position: &Position,
+    ) -> u8 {
+        match self {
+            BoardShift::Left => position.x,
+            BoardShift::Right => board_size - 1 - position.x,
+            BoardShift::Up => board_size - 1 - position.y,
+            BoardShift::Down => position.y,
+        }
+    }
+
+    /// The first column a tile at `position` can slide to: the edge, or
+    /// the cell just past the nearest blocker in the way. Blockers split a
+    /// row into segments that shift separately.
+    fn segment_start(
+        &self,
+        board_size: u8,
+        blockers: &[Position],
+        position: &Position,
+    ) -> u8 {
+        let row = self.get_row_position(position);
+        let column = self.column_index(board_size, position);
+        blockers
+            .iter()
+            .filter(|blocker| self.get_row_position(blocker) == row)
+            .map(|blocker| self.column_index(board_size, blocker))
+            .filter(|blocker_column| *blocker_column < column)
+            .map(|blocker_column| blocker_column + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
     fn get_row_position(
         &self,
         position: &Position,
@@ -130,20 +344,322 @@ impl BoardShift {
             BoardShift::Up | BoardShift::Down => position.x,
         }
     }
+
+    /// Whether the row `position` is in wraps around on a wraparound board.
+    /// A blocker cuts the ring, so rows with one in them keep their edges.
+    fn row_wraps(
+        &self,
+        wraparound: bool,
+        blockers: &[Position],
+        position: &Position,
+    ) -> bool {
+        let row = self.get_row_position(position);
+        wraparound
+            && !blockers
+                .iter()
+                .any(|blocker| self.get_row_position(blocker) == row)
+    }
+}
+
+impl BoardShift {
+    const ALL: [BoardShift; 4] = [
+        BoardShift::Left,
+        BoardShift::Right,
+        BoardShift::Up,
+        BoardShift::Down,
+    ];
+}
+
+/// What happens to a single tile when the board is shifted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TileMove {
+    /// The tile ends up at `position` holding `value`.
+    Slide { position: Position, value: u32 },
+    /// The tile was merged into its neighbour and disappears.
+    Merged,
+    /// The tile was next to a bomb as it went off.
+    Cleared,
+}
+
+/// The outcome of shifting a set of tiles, worked out without touching the
+/// world so it can be used both to play a move and to look ahead for hints.
+struct ShiftPlan {
+    /// One entry per input tile, in the same order.
+    moves: Vec<TileMove>,
+    /// Points scored by the merges in this shift.
+    score: u32,
+}
+
+impl ShiftPlan {
+    /// Whether the shift moves or merges any tile at all.
+    fn changes(&self, tiles: &[(Position, u32)]) -> bool {
+        self.moves.iter().zip(tiles).any(|(tile_move, (position, value))| {
+            match tile_move {
+                TileMove::Slide { position: new_position, value: new_value } => {
+                    new_position != position || new_value != value
+                }
+                TileMove::Merged | TileMove::Cleared => true,
+            }
+        })
+    }
+}
+
+fn plan_shift(
+    board_shift: BoardShift,
+    board_size: u8,
+    rule: &dyn MergeRule,
+    wraparound: bool,
+    blockers: &[Position],
+    tiles: &[(Position, u32)],
+    specials: &[Option<Special>],
+) -> ShiftPlan {
+    if rule.moves_one_cell() {
+        return plan_step(board_shift, board_size, rule, wraparound, blockers, tiles, specials);
+    }
+    let mut moves: Vec<TileMove> = tiles
+        .iter()
+        .map(|&(position, value)| TileMove::Slide { position, value })
+        .collect();
+    let mut score = 0;
+    let mut bombs = Vec::new();
+
+    let run = rule.run_length();
+    let mut order = shift_order(board_shift, board_size, wraparound, blockers, tiles);
+    merge_full_rings_across_the_edge(
+        board_shift,
+        board_size,
+        wraparound,
+        blockers,
+        tiles,
+        run,
+        &mut order,
+        |group| {
+            let run_tiles: Vec<(u32, Option<Special>)> = group
+                .iter()
+                .map(|&other| (tiles[other].1, specials[other]))
+                .collect();
+            merge_value(rule, &run_tiles).is_some()
+        },
+    );
+
+    // Tiles only slide and merge within the stretch of a row between
+    // blockers, so a change of segment starts the columns over
+    let segment = |position: &Position| {
+        (
+            board_shift.get_row_position(position),
+            board_shift.segment_start(board_size, blockers, position),
+        )
+    };
+    let mut column: u8 = 0;
+    let mut last_segment = None;
+    let mut next = 0;
+
+    while next < order.len() {
+        let index = order[next];
+        let (mut position, mut value) = tiles[index];
+        let current_segment = segment(&position);
+        if last_segment != Some(current_segment) {
+            column = current_segment.1;
+            last_segment = Some(current_segment);
+        }
+        board_shift.set_column_position(
+            board_size,
+            &mut position,
+            column
+        );
+
+        // This tile and the ones behind it in the same segment, enough of
+        // them to make a merge
+        let group: Vec<usize> = order[next..]
+            .iter()
+            .copied()
+            .take(run)
+            .take_while(|&other| segment(&tiles[other].0) == current_segment)
+            .collect();
+        let merged = if group.len() == run {
+            let run_tiles: Vec<(u32, Option<Special>)> = group
+                .iter()
+                .map(|&other| (tiles[other].1, specials[other]))
+                .collect();
+            merge_value(rule, &run_tiles)
+        } else {
+            None
+        };
+
+        if let Some(merged) = merged {
+            value = merged;
+
+            // Add tile value to score when successfully merged
+            score += value;
+            for &other in &group[1..] {
+                moves[other] = TileMove::Merged;
+            }
+            if group.iter().any(|&other| specials[other] == Some(Special::Bomb)) {
+                bombs.push(position);
+            }
+            next += run;
+        } else {
+            next += 1;
+        }
+        moves[index] = TileMove::Slide { position, value };
+        column += 1;
+    }
+
+    set_off_bombs(&mut moves, &bombs);
+    ShiftPlan { moves, score }
+}
+
+/// Shifts every tile a single cell, for rules that move one cell at a
+/// time. Working back from the edge, a tile steps into the cell ahead if
+/// it's empty by then, or merges with the tile there if that one hasn't
+/// merged already this move. Blockers and the edge hold tiles in place.
+fn plan_step(
+    board_shift: BoardShift,
+    board_size: u8,
+    rule: &dyn MergeRule,
+    wraparound: bool,
+    blockers: &[Position],
+    tiles: &[(Position, u32)],
+    specials: &[Option<Special>],
+) -> ShiftPlan {
+    let mut moves: Vec<TileMove> = tiles
+        .iter()
+        .map(|&(position, value)| TileMove::Slide { position, value })
+        .collect();
+    let mut score = 0;
+    let mut bombs = Vec::new();
+
+    let order = shift_order(board_shift, board_size, wraparound, blockers, tiles);
+    let mut occupants: HashMap<Position, usize> = tiles
+        .iter()
+        .enumerate()
+        .map(|(index, (position, _))| (*position, index))
+        .collect();
+    let mut merged = HashSet::new();
+
+    for index in order {
+        // Already landed, having been merged into
+        if merged.contains(&index) {
+            continue;
+        }
+        let (position, value) = tiles[index];
+        let column = board_shift.column_index(board_size, &position);
+        let wraps = board_shift.row_wraps(wraparound, blockers, &position);
+        if column == 0 && !wraps {
+            continue;
+        }
+        let mut ahead = position;
+        board_shift.set_column_position(board_size, &mut ahead, column + board_size - 1);
+        if blockers.contains(&ahead) {
+            continue;
+        }
+        match occupants.get(&ahead).copied() {
+            None => {
+                occupants.remove(&position);
+                occupants.insert(ahead, index);
+                moves[index] = TileMove::Slide { position: ahead, value };
+            }
+            Some(other) if !merged.contains(&other) => {
+                let TileMove::Slide { value: other_value, .. } = moves[other] else {
+                    continue;
+                };
+                let pair = [(other_value, specials[other]), (value, specials[index])];
+                let Some(value) = merge_value(rule, &pair) else {
+                    continue;
+                };
+                score += value;
+                occupants.remove(&position);
+                merged.insert(other);
+                moves[other] = TileMove::Slide { position: ahead, value };
+                moves[index] = TileMove::Merged;
+                if pair.iter().any(|(_, special)| *special == Some(Special::Bomb)) {
+                    bombs.push(ahead);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    set_off_bombs(&mut moves, &bombs);
+    ShiftPlan { moves, score }
+}
+
+/// The order tiles are shifted in: row by row, from the edge they're
+/// shifted towards. On a wraparound board the leading tiles of a row are
+/// pushed across the edge to the back of it, so a row that wraps starts
+/// from the first tile with a gap ahead of it instead.
+fn shift_order(
+    board_shift: BoardShift,
+    board_size: u8,
+    wraparound: bool,
+    blockers: &[Position],
+    tiles: &[(Position, u32)],
+) -> Vec<usize> {
+    let sorted = (0..tiles.len())
+        .sorted_by(|&a, &b| board_shift.sort(&tiles[a].0, &tiles[b].0))
+        .group_by(|&index| board_shift.get_row_position(&tiles[index].0));
+    let mut order = Vec::with_capacity(tiles.len());
+    for (_, row) in &sorted {
+        let mut row: Vec<usize> = row.collect();
+        if board_shift.row_wraps(wraparound, blockers, &tiles[row[0]].0) {
+            let columns: Vec<u8> = row
+                .iter()
+                .map(|&index| board_shift.column_index(board_size, &tiles[index].0))
+                .collect();
+            let behind = |i: usize| columns[(i + columns.len() - 1) % columns.len()];
+            // A full row has no gap anywhere and stays as it is
+            let start = (0..row.len())
+                .find(|&i| (behind(i) + 1) % board_size != columns[i])
+                .unwrap_or(0);
+            row.rotate_left(start);
+        }
+        order.extend(row);
+    }
+    order
 }
 
-impl TryFrom<&KeyCode> for BoardShift {
-    type Error = &'static str;
+/// A full row that wraps has no gap to start from, so it would otherwise
+/// always start at the edge and the tiles either side of it could never
+/// meet. When the tiles across the edge make a run that merges, the row
+/// starts with them instead.
+#[allow(clippy::too_many_arguments)]
+fn merge_full_rings_across_the_edge(
+    board_shift: BoardShift,
+    board_size: u8,
+    wraparound: bool,
+    blockers: &[Position],
+    tiles: &[(Position, u32)],
+    run: usize,
+    order: &mut [usize],
+    merges: impl Fn(&[usize]) -> bool,
+) {
+    for row in order.chunk_by_mut(|&a, &b| {
+        board_shift.get_row_position(&tiles[a].0) == board_shift.get_row_position(&tiles[b].0)
+    }) {
+        if row.len() != usize::from(board_size)
+            || run > row.len()
+            || !board_shift.row_wraps(wraparound, blockers, &tiles[row[0]].0)
+        {
+            continue;
+        }
+        let across = (1..run).find(|&back| {
+            let mut ring = row.to_vec();
+            ring.rotate_right(back);
+            merges(&ring[..run])
+        });
+        if let Some(back) = across {
+            row.rotate_right(back);
+        }
+    }
+}
 
-    fn try_from(
-        value: &KeyCode,
-    ) -> Result<Self, Self::Error> {
-        match value {
-            KeyCode::Left => Ok(BoardShift::Left),
-            KeyCode::Up => Ok(BoardShift::Up),
-            KeyCode::Right => Ok(BoardShift::Right),
-            KeyCode::Down => Ok(BoardShift::Down),
-            _ => Err("not a valid board_shift key"),
+/// Bombs go off once everything has landed, taking out their neighbours.
+fn set_off_bombs(moves: &mut [TileMove], bombs: &[Position]) {
+    for tile_move in moves.iter_mut() {
+        if let TileMove::Slide { position, .. } = tile_move {
+            if bombs.iter().any(|bomb| bomb.is_next_to(position)) {
+                *tile_move = TileMove::Cleared;
+            }
         }
     }
 }
@@ -160,43 +676,493 @@ impl FromWorld for FontSpec {
     }
 }
 
-struct NewTileEvent;
+/// Sent after a move, carrying the direction the tiles were shifted.
+struct NewTileEvent(BoardShift);
+
+/// Sent when a new tile appears on the board after a move.
+struct TileSpawnedEvent;
+
+/// Sent for every pair of tiles merged by a shift, carrying the merged
+/// tile's position and its new value (which is also what it adds to the score).
+struct MergeEvent {
+    position: Position,
+    value: u32,
+}
+
+/// Rules a game is played under.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    /// No undo and no hints.
+    Hardcore,
+    /// As many undos and hints as the player likes, with its scores kept
+    /// apart from the others.
+    Practice,
+    /// As high a score as possible before the clock runs out.
+    Blitz,
+    /// A target to reach within a limited number of moves.
+    Puzzle,
+    /// The same seeded game for everyone each day, started from the daily
+    /// challenge screen rather than picked in the settings.
+    Daily,
+    /// No game over: when the board locks up, the smallest tiles are cleared
+    /// to make room and play carries on.
+    Zen,
+}
+
+impl GameMode {
+    /// Modes that can be picked in the settings, each with its own leaderboards.
+    pub const ALL: [GameMode; 6] = [
+        GameMode::Classic,
+        GameMode::Hardcore,
+        GameMode::Practice,
+        GameMode::Blitz,
+        GameMode::Puzzle,
+        GameMode::Zen,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::Hardcore => "Hardcore",
+            GameMode::Practice => "Practice",
+            GameMode::Blitz => "Blitz",
+            GameMode::Puzzle => "Puzzle",
+            GameMode::Daily => "Daily",
+            GameMode::Zen => "Zen",
+        }
+    }
+
+    /// How many moves back can be undone, or `None` for no limit.
+    fn undo_limit(&self) -> Option<usize> {
+        match self {
+            GameMode::Classic
+            | GameMode::Blitz
+            | GameMode::Puzzle
+            | GameMode::Daily
+            | GameMode::Zen => Some(History::LIMIT),
+            GameMode::Hardcore => Some(0),
+            GameMode::Practice => None,
+        }
+    }
+
+    /// Seconds of play the game lasts, if it's against the clock.
+    fn time_limit(&self) -> Option<f32> {
+        match self {
+            GameMode::Blitz => Some(180.0),
+            _ => None,
+        }
+    }
+
+    /// Move budget and target, for modes that have them.
+    fn puzzle(&self) -> Option<Puzzle> {
+        match self {
+            GameMode::Puzzle => Some(Puzzle::DEFAULT),
+            _ => None,
+        }
+    }
+
+    /// Whether a locked up board is cleared out rather than ending the game.
+    fn endless(&self) -> bool {
+        *self == GameMode::Zen
+    }
+
+    fn allows_hints(&self) -> bool {
+        *self != GameMode::Hardcore
+    }
+
+    /// Whether scores in this mode are eligible for the leaderboard: only
+    /// hardcore ones, played without undo or hints.
+    pub fn leaderboard_eligible(&self) -> bool {
+        *self == GameMode::Hardcore
+    }
+}
+
+/// What a puzzle has to achieve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PuzzleGoal {
+    Tile(u32),
+    Score(u32),
+}
+
+impl PuzzleGoal {
+    fn reached(&self, score: u32, max_tile: u32) -> bool {
+        match self {
+            PuzzleGoal::Tile(tile) => max_tile >= *tile,
+            PuzzleGoal::Score(target) => score >= *target,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            PuzzleGoal::Tile(tile) => format!("Reach {}", tile),
+            PuzzleGoal::Score(target) => format!("Score {}", target),
+        }
+    }
+}
+
+/// A goal to reach within a budget of moves. Only moves that change the
+/// board use up the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub moves: u32,
+    pub goal: PuzzleGoal,
+}
+
+impl Puzzle {
+    const DEFAULT: Puzzle = Puzzle {
+        moves: 150,
+        goal: PuzzleGoal::Tile(256),
+    };
+}
+
+/// Where the tiles that appear after each move come from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Spawns {
+    Random,
+    /// Random, but from a fixed seed so every attempt plays out the same.
+    Seed(u64),
+    /// Exactly these tiles, in order, skipping any whose cell is taken.
+    /// Nothing more spawns once they run out.
+    Sequence(Vec<(Position, u32)>),
+}
+
+/// A game with fixed rules and a fixed start, rather than one set up from
+/// the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSpec {
+    pub board_size: u8,
+    pub mode: GameMode,
+    pub puzzle: Option<Puzzle>,
+    /// Chance that a tile spawned at random is a 4 rather than a 2.
+    pub four_chance: f32,
+    /// Starting tiles, or two random ones if empty.
+    pub tiles: Vec<(Position, u32)>,
+    pub spawns: Spawns,
+    /// Cells blocked off for the whole game.
+    #[serde(default)]
+    pub blockers: Vec<Position>,
+    /// Whether wildcards, bombs and doublers turn up among the new tiles.
+    #[serde(default)]
+    pub special_tiles: bool,
+    #[serde(default)]
+    pub merge_rules: MergeRules,
+    /// Whether tiles pushed off one edge come back in on the opposite one.
+    #[serde(default)]
+    pub wraparound: bool,
+    /// Score carried over from before the starting tiles, for positions
+    /// picked up partway through a game.
+    #[serde(default)]
+    pub score: u32,
+}
+
+/// The setup new games start from, or `None` to use the settings.
+#[derive(Default, Resource)]
+struct CustomGame(Option<GameSpec>);
+
+/// Picks the cells and values of new tiles for the current game.
+#[derive(Resource)]
+struct TileSpawner {
+    rng: StdRng,
+    four_chance: f32,
+    merge_rules: MergeRules,
+    special_tiles: bool,
+    sequence: Option<VecDeque<(Position, u32)>>,
+    /// The value of the next tile to enter, dealt ahead for rules that
+    /// show it.
+    upcoming: Option<u32>,
+}
+
+impl Default for TileSpawner {
+    fn default() -> Self {
+        TileSpawner::new(&Spawns::Random, 0.1, false, MergeRules::Classic)
+    }
+}
+
+impl TileSpawner {
+    /// Chance that a new tile is special, when special tiles are on.
+    const SPECIAL_CHANCE: f64 = 0.08;
+
+    fn new(
+        spawns: &Spawns,
+        four_chance: f32,
+        special_tiles: bool,
+        merge_rules: MergeRules,
+    ) -> Self {
+        let mut spawner = TileSpawner {
+            rng: match spawns {
+                Spawns::Seed(seed) => StdRng::seed_from_u64(*seed),
+                Spawns::Random | Spawns::Sequence(_) => StdRng::from_entropy(),
+            },
+            four_chance,
+            merge_rules,
+            special_tiles,
+            sequence: match spawns {
+                Spawns::Sequence(tiles) => Some(tiles.iter().copied().collect()),
+                Spawns::Random | Spawns::Seed(_) => None,
+            },
+            upcoming: None,
+        };
+        if merge_rules.rule().moves_one_cell() && spawner.sequence.is_none() {
+            spawner.upcoming = Some(spawner.next_value());
+        }
+        spawner
+    }
+
+    /// The next tile to place on a board of `board_size`, avoiding the
+    /// cells in `taken`.
+    fn next(&mut self, board_size: u8, taken: &[Position]) -> Option<(Position, u32)> {
+        if let Some(sequence) = &mut self.sequence {
+            while let Some((position, value)) = sequence.pop_front() {
+                if !taken.contains(&position) {
+                    return Some((position, value));
+                }
+            }
+            return None;
+        }
+        let position = (0..board_size)
+            .cartesian_product(0..board_size)
+            .map(|(x, y)| Position { x, y })
+            .filter(|position| !taken.contains(position))
+            .choose(&mut self.rng)?;
+        Some((position, self.next_value()))
+    }
+
+    /// The tile to place after a move towards `board_shift`. Under rules
+    /// that move one cell it enters on the edge the tiles moved away from,
+    /// taking the value dealt ahead.
+    fn next_after(
+        &mut self,
+        board_shift: BoardShift,
+        board_size: u8,
+        taken: &[Position],
+    ) -> Option<(Position, u32)> {
+        let Some(value) = self.upcoming else {
+            return self.next(board_size, taken);
+        };
+        let free = (0..board_size)
+            .cartesian_product(0..board_size)
+            .map(|(x, y)| Position { x, y })
+            .filter(|position| !taken.contains(position));
+        // Anywhere free will do when the far edge is full
+        let position = free
+            .clone()
+            .filter(|position| board_shift.column_index(board_size, position) == board_size - 1)
+            .choose(&mut self.rng)
+            .or_else(|| free.choose(&mut self.rng))?;
+        self.upcoming = Some(self.next_value());
+        Some((position, value))
+    }
+
+    /// A value for a new tile under the current rules.
+    fn next_value(&mut self) -> u32 {
+        let rule = self.merge_rules.rule();
+        if let Some(deck) = rule.spawn_deck() {
+            return *deck.choose(&mut self.rng).expect("deck to have values");
+        }
+        let values = rule.spawn_values();
+        if self.rng.gen_bool(f64::from(self.four_chance.clamp(0.0, 1.0))) {
+            values.1
+        } else {
+            values.0
+        }
+    }
+
+    /// Whether the tile just placed is a special one. Tiles from a fixed
+    /// sequence never are.
+    fn next_special(&mut self) -> Option<Special> {
+        if !self.special_tiles
+            || self.sequence.is_some()
+            || !self.rng.gen_bool(Self::SPECIAL_CHANCE)
+        {
+            return None;
+        }
+        Special::ALL.choose(&mut self.rng).copied()
+    }
+}
 
 #[derive(Default, Resource)]
 struct  Game {
     score: u32,
     score_best: u32,
+    mode: GameMode,
+    /// Moves that changed the board.
+    moves: u32,
+    merges: u32,
+    /// Seconds spent playing, not counting time paused or in menus.
+    duration: f32,
+    puzzle: Option<Puzzle>,
+    /// Whether the puzzle's goal has been reached.
+    solved: bool,
+    /// The setup this game started from, so it can be played again exactly.
+    spec: Option<GameSpec>,
+    merge_rules: MergeRules,
+    wraparound: bool,
+}
+
+impl Game {
+    /// Seconds left before a timed game ends.
+    fn time_left(&self) -> Option<f32> {
+        self.mode
+            .time_limit()
+            .map(|limit| (limit - self.duration).max(0.0))
+    }
+
+    /// Moves left in a puzzle's budget.
+    fn moves_left(&self) -> Option<u32> {
+        self.puzzle
+            .map(|puzzle| puzzle.moves.saturating_sub(self.moves))
+    }
+}
+
+/// Summary of a game once it's over, whether it ran out of moves or was
+/// abandoned.
+#[derive(Debug, Clone)]
+struct GameRecord {
+    board_size: u8,
+    mode: GameMode,
+    spec: Option<GameSpec>,
+    solved: bool,
+    score: u32,
+    max_tile: u32,
+    moves: u32,
+    merges: u32,
+    duration: f32,
+    /// Whether the score is eligible for the leaderboard.
+    eligible: bool,
+}
+
+impl GameRecord {
+    /// Whether the game reached the winning tile of the rules it was played
+    /// under.
+    fn won(&self) -> bool {
+        let rules = self.spec.as_ref().map(|spec| spec.merge_rules).unwrap_or_default();
+        self.max_tile >= rules.rule().winning_tile()
+    }
+}
+
+struct GameFinishedEvent(GameRecord);
+
+/// The tile a game has to reach to count as won.
+const WINNING_TILE: u32 = 2048;
+
+/// The tiles and score as they were before a move, so it can be undone.
+struct Snapshot {
+    tiles: Vec<(Position, u32)>,
+    /// The special rule of each tile, in the same order.
+    specials: Vec<Option<Special>>,
+    score: u32,
+    /// The tile dealt ahead before the move, shown again once it's undone.
+    upcoming: Option<u32>,
+}
+
+#[derive(Default, Resource)]
+struct History {
+    snapshots: Vec<Snapshot>,
+}
+
+impl History {
+    /// How many moves back can be undone in a classic game.
+    const LIMIT: usize = 100;
+
+    /// Records a move, forgetting the oldest ones past `limit`.
+    fn push(&mut self, snapshot: Snapshot, limit: Option<usize>) {
+        self.snapshots.push(snapshot);
+        if let Some(limit) = limit {
+            let excess = self.snapshots.len().saturating_sub(limit);
+            self.snapshots.drain(..excess);
+        }
+    }
+    fn pop(&mut self) -> Option<Snapshot> {
+        self.snapshots.pop()
+    }
+    fn clear(&mut self) {
+        self.snapshots.clear();
+    }
 }
 
+/// The move suggested to the player, cleared as soon as they make one.
+#[derive(Default, Resource)]
+struct Hint(Option<BoardShift>);
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum RunState {
+    MainMenu,
     Playing,
+    Paused,
     GameOver,
+    Settings,
+    Bindings,
+    Confirm,
+    Stats,
+    Leaderboard,
+    LevelSelect,
+    Daily,
+    Share,
+    EnterCode,
+    Editor,
 }
 
 fn main() {
+    let challenge = challenge_arg().or_else(board_arg);
+    let first_state = if challenge.is_some() {
+        RunState::Playing
+    } else {
+        RunState::MainMenu
+    };
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugin(GameUiPlugin)
+        .add_plugin(LayoutPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(SettingsPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(LeaderboardPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(LevelsPlugin)
+        .add_plugin(DailyPlugin)
+        .add_plugin(ChallengePlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(NotationPlugin)
         .add_plugin(EasingsPlugin)
         .init_resource::<FontSpec>()
         .init_resource::<Game>()
+        .init_resource::<History>()
+        .init_resource::<Hint>()
+        .insert_resource(CustomGame(challenge))
+        .init_resource::<TileSpawner>()
         .add_event::<NewTileEvent>()
-        .add_state(RunState::Playing)
+        .add_event::<TileSpawnedEvent>()
+        .add_event::<MergeEvent>()
+        .add_event::<GameFinishedEvent>()
+        .add_state(first_state)
         .add_startup_system(setup)
         .add_startup_system(spawn_board)
         .add_system_set(
             SystemSet::on_update(RunState::Playing)
                 .with_system(render_tile_points)
                 .with_system(board_shift)
+                .with_system(undo_move)
+                .with_system(show_hint)
                 .with_system(render_tiles)
                 .with_system(new_tile_handler)
                 .with_system(end_game)
+                .with_system(track_play_time)
+                .with_system(end_timed_game.after(end_game).after(track_play_time))
+                .with_system(end_puzzle.after(end_game).after(board_shift))
+        )
+        .add_system(restart_game)
+        .add_system(despawn_cleared_tiles)
+        .add_system_set(
+            SystemSet::on_exit(RunState::Playing)
+                .with_system(finish_game)
         )
         .add_system_set(
             SystemSet::on_enter(RunState::Playing)
                 .with_system(game_reset)
-                .with_system(spawn_tiles)
+                .with_system(apply_board_size.after(game_reset).before(spawn_tiles))
+                .with_system(spawn_tiles.after(game_reset))
         )
         .run()
 }
@@ -208,8 +1174,9 @@ fn setup(mut commands: Commands) {
 
 fn spawn_board(
     mut commands: Commands,
+    settings: Res<Settings>,
 ) {
-    let board = Board::new(4);
+    let board = Board::new(settings.board_size);
     let board_sprite = Sprite{
         custom_size: Some(Vec2::new(
             board.physical_size,
@@ -219,138 +1186,235 @@ fn spawn_board(
         ..Sprite::default()
     };
 
+    commands
+        .spawn(SpriteBundle {
+            sprite: board_sprite,
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            spawn_placeholders(builder, &board);
+        }).insert(board);
+}
+
+fn spawn_placeholders(
+    builder: &mut ChildBuilder,
+    board: &Board,
+) {
     let tile_sprite = Sprite {
         custom_size: Some(Vec2::new(
-            TILE_SIZE,
-            TILE_SIZE,
+            board.tile_size,
+            board.tile_size,
         )),
         color: MATERIALS.tile_placeholder,
         ..Default::default()
     };
 
-    commands
-        .spawn(SpriteBundle {
-            sprite: board_sprite,
+    for tile in (0..board.size).cartesian_product(0..board.size) {
+        builder.spawn(SpriteBundle {
+            sprite: tile_sprite.clone(),
+            transform: Transform::from_xyz(
+                board.cell_position_to_physical(tile.0), 
+                board.cell_position_to_physical(tile.1), 
+                1.0,
+            ),
             ..Default::default()
         })
-        .with_children(|builder| {
-            for tile in (0..board.size).cartesian_product(0..board.size) {
-                builder.spawn(SpriteBundle {
-                    sprite: tile_sprite.clone(),
-                    transform: Transform::from_xyz(
-                        board.cell_position_to_physical(tile.0), 
-                        board.cell_position_to_physical(tile.1), 
-                        1.0,
-                    ),
-                    ..Default::default()
-                });
-            }   
-        }).insert(board);
+        .insert(TilePlaceholder(Position { x: tile.0, y: tile.1 }));
+    }
+}
+
+/// Rebuilds the board when a new game starts with a different board size
+/// setting, keeping it the same size on screen.
+fn apply_board_size(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    game: Res<Game>,
+    mut boards: Query<(Entity, &mut Board)>,
+    placeholders: Query<Entity, With<TilePlaceholder>>,
+) {
+    let size = game
+        .spec
+        .as_ref()
+        .map_or(settings.board_size, |spec| spec.board_size);
+    resize_board(&mut commands, &mut boards, &placeholders, size);
+}
+
+/// Changes how many cells the board has, keeping it the same size on screen.
+fn resize_board(
+    commands: &mut Commands,
+    boards: &mut Query<(Entity, &mut Board)>,
+    placeholders: &Query<Entity, With<TilePlaceholder>>,
+    size: u8,
+) {
+    let (entity, mut board) = boards.single_mut();
+    if board.size == size {
+        return;
+    }
+    for placeholder in placeholders.iter() {
+        commands.entity(placeholder).despawn_recursive();
+    }
+    let extent = board.physical_size;
+    board.size = size;
+    board.fit_to(extent);
+    commands.entity(entity).with_children(|builder| {
+        spawn_placeholders(builder, &board);
+    });
 }
 
 fn spawn_tiles(
     mut commands: Commands,
     query_board: Query<&Board>,
     font_spec: Res<FontSpec>,
+    game: Res<Game>,
+    mut spawner: ResMut<TileSpawner>,
 ) {
     let board = query_board
         .single();
-    
-    let mut rng = rand::thread_rng();
-    let starting_tiles: Vec<(u8, u8)> = (0..board.size)
-        .cartesian_product(0..board.size)
-        .choose_multiple(&mut rng, 2);
-    
-    for (x, y) in starting_tiles.iter() {
-        let pos = Position { x: *x, y: *y };
 
+    let blockers = game
+        .spec
+        .as_ref()
+        .map_or(Vec::new(), |spec| spec.blockers.clone());
+    for &pos in &blockers {
+        spawn_blocker(&mut commands, board, pos);
+    }
+
+    let starting_tiles = match &game.spec {
+        Some(spec) if !spec.tiles.is_empty() => spec.tiles.clone(),
+        _ => {
+            let mut tiles: Vec<(Position, u32)> = Vec::new();
+            for _ in 0..2 {
+                let taken: Vec<Position> = tiles
+                    .iter()
+                    .map(|(pos, _)| *pos)
+                    .chain(blockers.iter().copied())
+                    .collect();
+                tiles.extend(spawner.next(board.size, &taken));
+            }
+            tiles
+        }
+    };
+    
+    for (pos, value) in starting_tiles {
         spawn_tile(
             &mut commands,
             board,
             &font_spec,
             pos,
+            value,
+            None,
         )
     }
 }
 
 fn render_tile_points(
     mut texts: Query<&mut Text, With<TileText>>,
-    tiles: Query<(&Points, &Children)>,
+    tiles: Query<(&Points, &Children, SpecialTile)>,
 ) {
-    for (points, children) in tiles.iter() {
+    for (points, children, special) in tiles.iter() {
         if let Some(entity) = children.first() {
             let mut text = texts
                 .get_mut(*entity)
                 .expect("expected Text to exits");
             let mut text_section = text.sections.first_mut().expect("expect first section to be accessible as mutable");
-            text_section.value = points.value.to_string()
+            text_section.value = tile_label(points.value, Special::of(special))
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn board_shift(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut tiles: Query<(Entity, &mut Position, &mut Points)>,
+    mut action_reader: EventReader<ActionEvent>,
+    mut tiles: Query<(Entity, &mut Position, &mut Points, SpecialTile, Option<&Children>)>,
+    blockers: Query<&Position, (With<Blocker>, Without<Points>)>,
+    badges: Query<Entity, With<SpecialBadge>>,
     mut tile_writer: EventWriter<NewTileEvent>,
+    mut merge_writer: EventWriter<MergeEvent>,
     query_board: Query<&Board>,
-    mut game: ResMut<Game>
+    spawner: Res<TileSpawner>,
+    mut game: ResMut<Game>,
+    mut history: ResMut<History>,
+    mut hint: ResMut<Hint>,
 ) {
     let board = query_board.single();
 
-    let shift_direction = 
-        keyboard_input.get_just_pressed().find_map(
-            |key_code| BoardShift::try_from(key_code).ok(),
-        );
-    
+    let shift_direction = action_reader
+        .iter()
+        .find_map(|ActionEvent(action)| action.board_shift());
+
     if let Some(board_shift) = shift_direction {
-            dbg!(&shift_direction);
-            let mut it = tiles
-                .iter_mut()
-                .sorted_by(|a, b| board_shift.sort(&a.1, &b.1))
-                .peekable();
-            
-                let mut column: u8 = 0;
-
-                while let Some(mut tile) = it.next() {
-                    board_shift.set_column_position(
-                        board.size, 
-                        &mut tile.1, 
-                        column
-                    );
-                    if let Some(tile_next) = it.peek() {
-                        if board_shift.get_row_position(&tile.1) != board_shift.get_row_position(&tile_next.1) 
-                        {
-                            column = 0;
-                        } else if tile.2.value != tile_next.2.value 
-                        {
-                            column = column + 1;
-                        } else {
-                            let real_next_tile = it
-                                .next()
-                                .expect("A peeked tile should always exist when we .next here");
-                            tile.2.value = tile.2.value + real_next_tile.2.value;
-
-                            // Add tile value to score when successfully merged
-                            game.score += tile.2.value;
+        let mut entities = Vec::new();
+        let mut snapshot = Vec::new();
+        let mut specials = Vec::new();
+        for (entity, position, points, special, _) in tiles.iter() {
+            entities.push(entity);
+            snapshot.push((*position, points.value));
+            specials.push(Special::of(special));
+        }
+
+        let blockers: Vec<Position> = blockers.iter().copied().collect();
+        let rule = game.merge_rules.rule();
+        let plan = plan_shift(
+            board_shift,
+            board.size,
+            rule,
+            game.wraparound,
+            &blockers,
+            &snapshot,
+            &specials,
+        );
+        // A move that doesn't move anything isn't a move: nothing spawns
+        // and it costs nothing
+        if !plan.changes(&snapshot) {
+            return;
+        }
+        game.moves += 1;
 
+        history.push(
+            Snapshot {
+                tiles: snapshot.clone(),
+                specials: specials.clone(),
+                score: game.score,
+                upcoming: spawner.upcoming,
+            },
+            game.mode.undo_limit(),
+        );
+        hint.0 = None;
+
+        for ((entity, tile_move), special) in entities.iter().zip(plan.moves).zip(specials) {
+            match tile_move {
+                TileMove::Slide { position, value } => {
+                    let (_, mut tile_position, mut points, _, children) = tiles
+                        .get_mut(*entity)
+                        .expect("tile to still exist");
+                    *tile_position = position;
+                    if points.value != value {
+                        points.value = value;
+                        game.merges += 1;
+                        merge_writer.send(MergeEvent { position, value });
+                        // A merged tile is an ordinary one
+                        if special.is_some() {
                             commands
-                                .entity(real_next_tile.0)
-                                .despawn_recursive();
-                        
-                            if let Some(future) = it.peek()
-                            {
-                                if board_shift.get_row_position(&tile.1) != board_shift.get_row_position(&future.1) 
-                                {
-                                    column = 0;
-                                } else {
-                                    column = column + 1;
+                                .entity(*entity)
+                                .remove::<(Wildcard, Bomb, Doubler)>();
+                            for child in children.into_iter().flatten() {
+                                if badges.contains(*child) {
+                                    commands.entity(*child).despawn_recursive();
                                 }
                             }
                         }
                     }
                 }
-                tile_writer.send(NewTileEvent);
+                TileMove::Merged | TileMove::Cleared => {
+                    commands
+                        .entity(*entity)
+                        .despawn_recursive();
+                }
+            }
+        }
+        game.score += plan.score;
+        tile_writer.send(NewTileEvent(board_shift));
     }
     if game.score_best < game.score {
         game.score_best = game.score;
@@ -364,6 +1428,7 @@ fn render_tiles(
         Changed<Position>,
     >,
     query_board: Query<&Board>,
+    settings: Res<Settings>,
 ) {
     let board = query_board.single();
     for (entity, transform, pos) in tiles.iter() {
@@ -378,7 +1443,7 @@ fn render_tiles(
             ),
             EaseFunction::QuadraticInOut,
             EasingType::Once {
-                duration: std::time::Duration::from_millis(200)
+                duration: settings.slide_duration()
             }
         ));
     }
@@ -397,36 +1462,26 @@ fn new_tile_handler(
     query_board: Query<&Board>,
     tiles: Query<&Position>,
     font_spec: Res<FontSpec>,
+    mut spawner: ResMut<TileSpawner>,
+    mut spawned_writer: EventWriter<TileSpawnedEvent>,
 ) {
     let board = query_board.single();
 
-    for _event in tile_reader.iter() {
-        let mut rng = rand::thread_rng();
-        let possible_position: Option<Position> = (
-            0..board.size)
-            .cartesian_product(0..board.size)
-            .filter_map(|tile_pos| {
-                let new_pos = Position {
-                    x: tile_pos.0,
-                    y: tile_pos.1,
-                };
-                match tiles
-                    .iter()
-                    .find(|&&pos| pos == new_pos)
-                {
-                    Some(_) => None,
-                    None => Some(new_pos),
-                }
-            })
-            .choose(&mut rng);
-    if let Some(pos) = possible_position {
-        spawn_tile(
-            &mut commands,
-            board,
-            &font_spec,
-            pos,
-        );
-    }}
+    for NewTileEvent(board_shift) in tile_reader.iter() {
+        let taken: Vec<Position> = tiles.iter().copied().collect();
+        if let Some((pos, value)) = spawner.next_after(*board_shift, board.size, &taken) {
+            let special = spawner.next_special();
+            spawn_tile(
+                &mut commands,
+                board,
+                &font_spec,
+                pos,
+                value,
+                special,
+            );
+            spawned_writer.send(TileSpawnedEvent);
+        }
+    }
 }
 
 fn spawn_tile(
@@ -434,17 +1489,19 @@ fn spawn_tile(
     board: &Board,
     font_spec: &Res<FontSpec>,
     pos: Position,
+    value: u32,
+    special: Option<Special>,
 ) {
     let tile_sprite = Sprite {
         color: MATERIALS.tile,
         custom_size: Some(Vec2::new(
-            TILE_SIZE,
-            TILE_SIZE,
+            board.tile_size,
+            board.tile_size,
         )),
         ..Default::default()
     };
 
-    commands.spawn(SpriteBundle {
+    let mut tile = commands.spawn(SpriteBundle {
         sprite: tile_sprite,
         transform: Transform::from_xyz(
             board.cell_position_to_physical(pos.x),
@@ -452,18 +1509,18 @@ fn spawn_tile(
             2.0,
         ),
         ..Default::default()
-    })
-    .with_children(|child_builder| {
+    });
+    tile.with_children(|child_builder| {
         child_builder
             .spawn(Text2dBundle {
                 text: Text::from_section(
-                    "2",
+                    tile_label(value, special),
                     TextStyle {
                         font: font_spec
                             .family
                             .clone(),
-                        font_size: 40.0,
-                        color: Color::BLACK,
+                        font_size: board.tile_size,
+                        color: MATERIALS.tile_text,
                         ..Default::default()
                     },
                 ).with_alignment(TextAlignment::CENTER),
@@ -473,64 +1530,596 @@ fn spawn_tile(
                 ..Default::default()
             })
             .insert(TileText);
+        // After the text, which has to stay the first child
+        if let Some(special) = special {
+            let (size, offset) = board.badge_geometry();
+            child_builder
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: special.color(),
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(offset),
+                    ..Default::default()
+                })
+                .insert(SpecialBadge);
+        }
     })
-    .insert(Points { value: 2 })
+    .insert(Points { value })
     .insert(pos);
+    match special {
+        Some(Special::Wildcard) => tile.insert(Wildcard),
+        Some(Special::Bomb) => tile.insert(Bomb),
+        Some(Special::Doubler) => tile.insert(Doubler),
+        None => &mut tile,
+    };
 }
 
-fn end_game(
-    tiles: Query<(&Position, &Points)>,
+/// What a tile shows: its value, or a question mark for a wildcard, which
+/// takes on the value of whatever it's merged with.
+fn tile_label(value: u32, special: Option<Special>) -> String {
+    match special {
+        Some(Special::Wildcard) => "?".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn spawn_blocker(
+    commands: &mut Commands,
+    board: &Board,
+    pos: Position,
+) {
+    commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: MATERIALS.blocker,
+            custom_size: Some(Vec2::new(
+                board.tile_size,
+                board.tile_size,
+            )),
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(
+            board.cell_position_to_physical(pos.x),
+            board.cell_position_to_physical(pos.y),
+            2.0,
+        ),
+        ..Default::default()
+    })
+    .insert(Blocker)
+    .insert(pos);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn undo_move(
+    mut commands: Commands,
+    mut action_reader: EventReader<ActionEvent>,
+    tiles: Query<Entity, (With<Position>, With<Points>)>,
+    query_board: Query<&Board>,
+    font_spec: Res<FontSpec>,
+    mut spawner: ResMut<TileSpawner>,
+    mut game: ResMut<Game>,
+    mut history: ResMut<History>,
+    mut hint: ResMut<Hint>,
+) {
+    if !action_reader
+        .iter()
+        .any(|ActionEvent(action)| *action == Action::Undo)
+    {
+        return;
+    }
+    let Some(snapshot) = history.pop() else {
+        return;
+    };
+    let board = query_board.single();
+
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for ((pos, value), special) in snapshot.tiles.into_iter().zip(snapshot.specials) {
+        spawn_tile(
+            &mut commands,
+            board,
+            &font_spec,
+            pos,
+            value,
+            special,
+        );
+    }
+    game.score = snapshot.score;
+    spawner.upcoming = snapshot.upcoming;
+    hint.0 = None;
+}
+
+fn show_hint(
+    mut action_reader: EventReader<ActionEvent>,
+    tiles: Query<(&Position, &Points, SpecialTile)>,
+    blockers: Query<&Position, With<Blocker>>,
     query_board: Query<&Board>,
+    game: Res<Game>,
+    mut hint: ResMut<Hint>,
+) {
+    if !action_reader
+        .iter()
+        .any(|ActionEvent(action)| *action == Action::Hint)
+    {
+        return;
+    }
+    if !game.mode.allows_hints() {
+        return;
+    }
+    let board = query_board.single();
+    let snapshot: Vec<(Position, u32)> = tiles
+        .iter()
+        .map(|(position, points, _)| (*position, points.value))
+        .collect();
+    let specials: Vec<Option<Special>> = tiles
+        .iter()
+        .map(|(_, _, special)| Special::of(special))
+        .collect();
+    let blockers: Vec<Position> = blockers.iter().copied().collect();
+
+    // Suggest whichever move that does something scores the most right away
+    hint.0 = BoardShift::ALL
+        .iter()
+        .filter_map(|&board_shift| {
+            let rule = game.merge_rules.rule();
+            let plan = plan_shift(
+            board_shift,
+            board.size,
+            rule,
+            game.wraparound,
+            &blockers,
+            &snapshot,
+            &specials,
+        );
+            plan.changes(&snapshot).then_some((board_shift, plan.score))
+        })
+        .max_by_key(|(_, score)| *score)
+        .map(|(board_shift, _)| board_shift);
+}
+
+fn restart_game(
+    mut action_reader: EventReader<ActionEvent>,
     mut run_state: ResMut<State<RunState>>,
+    mut confirmation: ResMut<Confirmation>,
+) {
+    if !action_reader
+        .iter()
+        .any(|ActionEvent(action)| *action == Action::Restart)
+    {
+        return;
+    }
+    match run_state.current() {
+        RunState::Playing => {
+            confirmation.ask(
+                &mut run_state,
+                "Abandon this game and restart?",
+                ButtonAction::RestartGame,
+            );
+        }
+        RunState::GameOver => {
+            let _ = run_state.set(RunState::Playing);
+        }
+        RunState::MainMenu
+        | RunState::Paused
+        | RunState::Settings
+        | RunState::Bindings
+        | RunState::Confirm
+        | RunState::Stats
+        | RunState::Leaderboard
+        | RunState::LevelSelect
+        | RunState::Daily
+        | RunState::Share
+        | RunState::EnterCode
+        | RunState::Editor => {}
+    }
+}
+
+fn track_play_time(
+    time: Res<Time>,
+    mut game: ResMut<Game>,
 ) {
+    game.duration += time.delta_seconds();
+}
+
+/// Reports the game that's being left, unless it never got past the
+/// starting tiles.
+fn finish_game(
+    game: Res<Game>,
+    tiles: Query<&Points>,
+    query_board: Query<&Board>,
+    mut finished_writer: EventWriter<GameFinishedEvent>,
+) {
+    if game.moves == 0 {
+        return;
+    }
     let board = query_board.single();
+    finished_writer.send(GameFinishedEvent(GameRecord {
+        board_size: board.size,
+        mode: game.mode,
+        spec: game.spec.clone(),
+        solved: game.solved,
+        score: game.score,
+        max_tile: tiles.iter().map(|points| points.value).max().unwrap_or(0),
+        moves: game.moves,
+        merges: game.merges,
+        duration: game.duration,
+        eligible: game.mode.leaderboard_eligible(),
+    }));
+}
 
-    if tiles.iter().len() == 16 {
-        let map: HashMap<&Position, &Points> = tiles.iter().collect();
+fn end_timed_game(
+    game: Res<Game>,
+    mut run_state: ResMut<State<RunState>>,
+) {
+    if game.time_left() == Some(0.0) {
+        // The board may have filled up on the same frame
+        let _ = run_state.set(RunState::GameOver);
+    }
+}
+
+fn end_puzzle(
+    mut game: ResMut<Game>,
+    tiles: Query<&Points>,
+    mut run_state: ResMut<State<RunState>>,
+) {
+    let Some(puzzle) = game.puzzle else {
+        return;
+    };
+    let max_tile = tiles.iter().map(|points| points.value).max().unwrap_or(0);
+    if puzzle.goal.reached(game.score, max_tile) {
+        game.solved = true;
+        let _ = run_state.set(RunState::GameOver);
+    } else if game.moves_left() == Some(0) {
+        let _ = run_state.set(RunState::GameOver);
+    }
+}
 
-        let neighbour_points = [(-1, 0), (0, 1), (1, 0), (0, -1)];
-        let board_range: Range<i8> = 0..(board.size as i8);
+/// Whether shifting the board in any direction would move or merge a tile.
+/// This asks the same planner the moves use, so a pair that looks like it
+/// could merge, but that no shift actually brings together, doesn't count.
+fn has_move(
+    board_size: u8,
+    rule: &dyn MergeRule,
+    wraparound: bool,
+    blockers: &[Position],
+    tiles: &[(Position, u32)],
+    specials: &[Option<Special>],
+) -> bool {
+    BoardShift::ALL.iter().any(|&board_shift| {
+        plan_shift(board_shift, board_size, rule, wraparound, blockers, tiles, specials)
+            .changes(tiles)
+    })
+}
 
-        let has_move = tiles.iter().any(
-            |(Position{x,y}, value)| {
-                neighbour_points
-                .iter()
-                .filter_map(|(x2, y2)| {
-                    let new_x = *x as i8 - x2;
-                    let new_y = *y as i8 - y2;
-
-                    // If there's space next to any of the tiles, the game continues.
-                    if !board_range.contains(&new_x)
-                        || !board_range.contains(&new_y)
-                    {
-                        return None;
-                    };
-
-                    // Grab the value of a tile that exists next to the tile that
-                    // we're comparing and then...
-                    map.get(&Position {
-                        x: new_x.try_into().unwrap(),
-                        y: new_y.try_into().unwrap(),
-                    })
-                })
-                // ... see if it can be merged
-                .any(|&v| v == value)
-            }
+#[allow(clippy::too_many_arguments)]
+fn end_game(
+    mut commands: Commands,
+    tiles: Query<(Entity, &Position, &Points, &Transform)>,
+    specials: Query<SpecialTile, With<Points>>,
+    blockers: Query<&Position, With<Blocker>>,
+    query_board: Query<&Board>,
+    game: Res<Game>,
+    settings: Res<Settings>,
+    mut run_state: ResMut<State<RunState>>,
+) {
+    let board = query_board.single();
+
+    // Blocked cells are never free, but never merge either
+    let cells = usize::from(board.size) * usize::from(board.size);
+    if tiles.iter().len() + blockers.iter().len() == cells {
+        let snapshot: Vec<(Position, u32)> = tiles
+            .iter()
+            .map(|(_, position, points, _)| (*position, points.value))
+            .collect();
+        let specials: Vec<Option<Special>> = tiles
+            .iter()
+            .map(|(entity, _, _, _)| specials.get(entity).ok().and_then(Special::of))
+            .collect();
+        let blockers: Vec<Position> = blockers.iter().copied().collect();
+
+        let has_move = has_move(
+            board.size,
+            game.merge_rules.rule(),
+            game.wraparound,
+            &blockers,
+            &snapshot,
+            &specials,
         );
-        if has_move == false {
-            dbg!("game over!");
-            run_state.set(RunState::GameOver).unwrap();
+        if !has_move {
+            if game.mode.endless() {
+                clear_smallest_tiles(&mut commands, &tiles, board, &settings);
+            } else {
+                let _ = run_state.set(RunState::GameOver);
+            }
+        }
+    }
+}
+
+/// Clears every tile of the lowest value off the board, shrinking them away
+/// where they're headed.
+fn clear_smallest_tiles(
+    commands: &mut Commands,
+    tiles: &Query<(Entity, &Position, &Points, &Transform)>,
+    board: &Board,
+    settings: &Settings,
+) {
+    let Some(smallest) = tiles.iter().map(|(_, _, points, _)| points.value).min() else {
+        return;
+    };
+    let duration = settings.slide_duration() * 2;
+    for (entity, position, points, transform) in tiles.iter() {
+        if points.value != smallest {
+            continue;
+        }
+        let target = Transform::from_xyz(
+            board.cell_position_to_physical(position.x),
+            board.cell_position_to_physical(position.y),
+            transform.translation.z,
+        )
+        .with_scale(Vec3::ZERO);
+        commands
+            .entity(entity)
+            .remove::<Position>()
+            .insert(Clearing(Timer::new(duration, TimerMode::Once)))
+            .insert(transform.ease_to(
+                target,
+                EaseFunction::QuadraticIn,
+                EasingType::Once { duration },
+            ));
+    }
+}
+
+fn despawn_cleared_tiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tiles: Query<(Entity, &mut Clearing)>,
+) {
+    for (entity, mut clearing) in tiles.iter_mut() {
+        if clearing.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn game_reset(
     mut commands: Commands,
     tiles: Query<Entity, With<Position>>,
     mut game: ResMut<Game>,
+    mut history: ResMut<History>,
+    mut hint: ResMut<Hint>,
+    mut spawner: ResMut<TileSpawner>,
+    custom_game: Res<CustomGame>,
+    settings: Res<Settings>,
 ) {
     for entity in tiles.iter() {
         commands.entity(entity).despawn_recursive();
     }
-    game.score = 0;
+    // Games set up from the settings still get a seed, so they can be shared
+    let spec = custom_game.0.clone().unwrap_or_else(|| GameSpec {
+        board_size: settings.board_size,
+        mode: settings.mode,
+        puzzle: settings.mode.puzzle(),
+        four_chance: settings.four_chance,
+        tiles: Vec::new(),
+        spawns: Spawns::Seed(u64::from(random::<u32>())),
+        blockers: (0..settings.board_size)
+            .cartesian_product(0..settings.board_size)
+            .map(|(x, y)| Position { x, y })
+            .choose_multiple(&mut thread_rng(), usize::from(settings.blockers)),
+        special_tiles: settings.special_tiles,
+        merge_rules: settings.merge_rules,
+        wraparound: settings.wraparound,
+        score: 0,
+    });
+    game.mode = spec.mode;
+    game.merge_rules = spec.merge_rules;
+    game.wraparound = spec.wraparound;
+    game.puzzle = spec.puzzle;
+    game.score = spec.score;
+    *spawner = TileSpawner::new(
+        &spec.spawns,
+        spec.four_chance,
+        spec.special_tiles,
+        spec.merge_rules,
+    );
+    game.spec = Some(spec);
+    game.solved = false;
+    game.moves = 0;
+    game.merges = 0;
+    game.duration = 0.0;
+    history.clear();
+    hint.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: u8, y: u8) -> Position {
+        Position { x, y }
+    }
+
+    fn shift(
+        board_shift: BoardShift,
+        blockers: &[Position],
+        tiles: &[(Position, u32)],
+    ) -> ShiftPlan {
+        let specials = vec![None; tiles.len()];
+        plan_shift(board_shift, 4, &Classic, false, blockers, tiles, &specials)
+    }
+
+    #[test]
+    fn slides_and_merges_pairs_once() {
+        let tiles = [(at(0, 0), 2), (at(1, 0), 2), (at(2, 0), 2), (at(3, 0), 2)];
+        let plan = shift(BoardShift::Left, &[], &tiles);
+        assert_eq!(
+            plan.moves,
+            vec![
+                TileMove::Slide { position: at(0, 0), value: 4 },
+                TileMove::Merged,
+                TileMove::Slide { position: at(1, 0), value: 4 },
+                TileMove::Merged,
+            ],
+        );
+        assert_eq!(plan.score, 8);
+    }
+
+    #[test]
+    fn unchanged_rows_are_no_move() {
+        let tiles = [(at(0, 0), 2), (at(1, 0), 4)];
+        assert!(!shift(BoardShift::Left, &[], &tiles).changes(&tiles));
+        assert!(shift(BoardShift::Right, &[], &tiles).changes(&tiles));
+    }
+
+    #[test]
+    fn blockers_split_rows() {
+        // 2 . x 2, shifted right: the blocker keeps the twos apart
+        let tiles = [(at(0, 0), 2), (at(3, 0), 2)];
+        let plan = shift(BoardShift::Right, &[at(2, 0)], &tiles);
+        assert_eq!(
+            plan.moves,
+            vec![
+                TileMove::Slide { position: at(1, 0), value: 2 },
+                TileMove::Slide { position: at(3, 0), value: 2 },
+            ],
+        );
+        assert_eq!(plan.score, 0);
+    }
+
+    #[test]
+    fn wildcards_and_doublers_merge() {
+        let tiles = [(at(0, 0), 8), (at(1, 0), 2), (at(0, 1), 4), (at(1, 1), 4)];
+        let specials = [None, Some(Special::Wildcard), Some(Special::Doubler), None];
+        let plan = plan_shift(BoardShift::Left, 4, &Classic, false, &[], &tiles, &specials);
+        assert_eq!(plan.moves[0], TileMove::Slide { position: at(0, 0), value: 16 });
+        assert_eq!(plan.moves[2], TileMove::Slide { position: at(0, 1), value: 16 });
+        assert_eq!(plan.score, 32);
+    }
+
+    #[test]
+    fn bombs_clear_neighbours_where_they_land() {
+        // The bomb merges at the left edge, taking out the eight just above
+        // but not the sixteen further off
+        let tiles = [(at(2, 0), 2), (at(3, 0), 2), (at(0, 1), 8), (at(3, 3), 16)];
+        let specials = [None, Some(Special::Bomb), None, None];
+        let plan = plan_shift(BoardShift::Left, 4, &Classic, false, &[], &tiles, &specials);
+        assert_eq!(plan.moves[0], TileMove::Slide { position: at(0, 0), value: 4 });
+        assert_eq!(plan.moves[1], TileMove::Merged);
+        assert_eq!(plan.moves[2], TileMove::Cleared);
+        assert_eq!(plan.moves[3], TileMove::Slide { position: at(0, 3), value: 16 });
+    }
+
+    #[test]
+    fn runs_of_three_merge_under_powers_of_three() {
+        let tiles = [(at(0, 0), 3), (at(1, 0), 3), (at(2, 0), 3), (at(3, 0), 3)];
+        let specials = [None; 4];
+        let plan = plan_shift(BoardShift::Left, 4, &PowersOfThree, false, &[], &tiles, &specials);
+        assert_eq!(
+            plan.moves,
+            vec![
+                TileMove::Slide { position: at(0, 0), value: 9 },
+                TileMove::Merged,
+                TileMove::Merged,
+                TileMove::Slide { position: at(1, 0), value: 3 },
+            ],
+        );
+    }
+
+    #[test]
+    fn threes_tiles_move_one_cell() {
+        // . 1 2 3 and 3 3 3 .: the first row steps along, and only the
+        // front pair of threes merges
+        let tiles = [
+            (at(1, 0), 1),
+            (at(2, 0), 2),
+            (at(3, 0), 3),
+            (at(0, 1), 3),
+            (at(1, 1), 3),
+            (at(2, 1), 3),
+        ];
+        let specials = [None; 6];
+        let plan = plan_shift(BoardShift::Left, 4, &Threes, false, &[], &tiles, &specials);
+        assert_eq!(
+            plan.moves,
+            vec![
+                TileMove::Slide { position: at(0, 0), value: 1 },
+                TileMove::Slide { position: at(1, 0), value: 2 },
+                TileMove::Slide { position: at(2, 0), value: 3 },
+                TileMove::Slide { position: at(0, 1), value: 6 },
+                TileMove::Merged,
+                TileMove::Slide { position: at(1, 1), value: 3 },
+            ],
+        );
+        assert_eq!(plan.score, 6);
+    }
+
+    #[test]
+    fn threes_merge_ones_and_twos() {
+        let tiles = [(at(0, 0), 1), (at(1, 0), 2), (at(2, 0), 1)];
+        let specials = [None; 3];
+        let plan = plan_shift(BoardShift::Left, 4, &Threes, false, &[], &tiles, &specials);
+        assert_eq!(
+            plan.moves,
+            vec![
+                TileMove::Slide { position: at(0, 0), value: 3 },
+                TileMove::Merged,
+                TileMove::Slide { position: at(1, 0), value: 1 },
+            ],
+        );
+    }
+
+    #[test]
+    fn threes_tiles_enter_on_the_far_edge() {
+        let mut spawner = TileSpawner::new(&Spawns::Seed(7), 0.1, false, MergeRules::Threes);
+        let upcoming = spawner.upcoming.expect("next tile to be dealt ahead");
+        let (position, value) = spawner
+            .next_after(BoardShift::Left, 4, &[])
+            .expect("room for a tile");
+        assert_eq!(value, upcoming);
+        assert_eq!(position.x, 3);
+        assert!(spawner.upcoming.is_some());
+    }
+
+    #[test]
+    fn wraparound_merges_across_the_edge() {
+        let tiles = [(at(0, 0), 2), (at(3, 0), 2)];
+        let specials = [None; 2];
+        let plan = plan_shift(BoardShift::Left, 4, &Classic, true, &[], &tiles, &specials);
+        assert_eq!(
+            plan.moves,
+            vec![TileMove::Merged, TileMove::Slide { position: at(0, 0), value: 4 }],
+        );
+        assert_eq!(plan.score, 4);
+    }
+
+    #[test]
+    fn full_wrapping_rows_merge_across_the_edge() {
+        // The only matching pair sits either side of the edge of a full row
+        let values = [
+            [2, 4, 8, 2],
+            [16, 32, 64, 128],
+            [256, 512, 1024, 2048],
+            [4096, 8192, 16384, 32768],
+        ];
+        let tiles: Vec<(Position, u32)> = (0..4u8)
+            .flat_map(|y| (0..4u8).map(move |x| (at(x, y), values[y as usize][x as usize])))
+            .collect();
+        let specials = vec![None; tiles.len()];
+        let plan = plan_shift(BoardShift::Left, 4, &Classic, true, &[], &tiles, &specials);
+        assert_eq!(
+            plan.moves[..4],
+            [
+                TileMove::Merged,
+                TileMove::Slide { position: at(1, 0), value: 4 },
+                TileMove::Slide { position: at(2, 0), value: 8 },
+                TileMove::Slide { position: at(0, 0), value: 4 },
+            ],
+        );
+        assert_eq!(plan.score, 4);
+        assert!(has_move(4, &Classic, true, &[], &tiles, &specials));
+        assert!(!has_move(4, &Classic, false, &[], &tiles, &specials));
+    }
 }
@@ -0,0 +1,401 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use std::{fmt, io::Read, str::FromStr};
+use crate::{
+    clipboard, spawn_blocker, spawn_tile, storage, Blocker, Board, ButtonAction,
+    ButtonActionEvent, FontSpec, Game, GameMode, GameSpec, MergeRules, Points, Position,
+    RunState, Sandbox, Spawns, BOARD_SIZES, MAX_VALUE,
+};
+
+/// Where positions are saved to and loaded from, in the data directory.
+const POSITION_FILE: &str = "position.txt";
+
+/// A board written out as text: a header with the size and score, then one
+/// line per row from the top, with `.` for an empty cell and `x` for a
+/// blocker. Lines starting with `#` are left out, so a position can carry
+/// a note.
+///
+/// ```text
+/// 4x4 score 1024
+///    2    .    .    4
+///    .  128    x    .
+///    .    .    .    .
+///   16    .    2    .
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardNotation {
+    pub size: u8,
+    pub score: u32,
+    pub tiles: Vec<(Position, u32)>,
+    pub blockers: Vec<Position>,
+}
+
+/// Why a board couldn't be read from text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    MissingHeader,
+    BadHeader(String),
+    UnsupportedSize(u8),
+    WrongRowCount { expected: u8, found: usize },
+    WrongRowLength { row: usize, expected: u8, found: usize },
+    BadValue(String),
+    /// A tile bigger than any the game can hold.
+    ValueTooBig(u32),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::MissingHeader => write!(f, "the board is empty"),
+            NotationError::BadHeader(header) => {
+                write!(f, "expected a header like \"4x4 score 0\", found \"{}\"", header)
+            }
+            NotationError::UnsupportedSize(size) => {
+                write!(f, "{0}x{0} boards aren't supported", size)
+            }
+            NotationError::WrongRowCount { expected, found } => {
+                write!(f, "expected {} rows, found {}", expected, found)
+            }
+            NotationError::WrongRowLength { row, expected, found } => {
+                write!(f, "row {} has {} cells instead of {}", row, found, expected)
+            }
+            NotationError::BadValue(value) => write!(f, "\"{}\" isn't a tile value", value),
+            NotationError::ValueTooBig(value) => {
+                write!(f, "{} is bigger than the biggest tile, {}", value, MAX_VALUE)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BoardNotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{0}x{0} score {1}", self.size, self.score)?;
+        let width = self
+            .tiles
+            .iter()
+            .map(|(_, value)| value.to_string().len())
+            .max()
+            .unwrap_or(1);
+        for y in (0..self.size).rev() {
+            let row: Vec<String> = (0..self.size)
+                .map(|x| {
+                    let position = Position { x, y };
+                    let cell = if self.blockers.contains(&position) {
+                        "x".to_string()
+                    } else {
+                        self.tiles
+                            .iter()
+                            .find(|(tile_position, _)| *tile_position == position)
+                            .map_or(".".to_string(), |(_, value)| value.to_string())
+                    };
+                    format!("{:>width$}", cell, width = width)
+                })
+                .collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BoardNotation {
+    type Err = NotationError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = lines.next().ok_or(NotationError::MissingHeader)?;
+        let (size, score) = parse_header(header)
+            .ok_or_else(|| NotationError::BadHeader(header.to_string()))?;
+        if !BOARD_SIZES.contains(&size) {
+            return Err(NotationError::UnsupportedSize(size));
+        }
+
+        let rows: Vec<&str> = lines.collect();
+        if rows.len() != usize::from(size) {
+            return Err(NotationError::WrongRowCount {
+                expected: size,
+                found: rows.len(),
+            });
+        }
+        let mut tiles = Vec::new();
+        let mut blockers = Vec::new();
+        for (row, line) in rows.iter().enumerate() {
+            let cells: Vec<&str> = line.split_whitespace().collect();
+            if cells.len() != usize::from(size) {
+                return Err(NotationError::WrongRowLength {
+                    row: row + 1,
+                    expected: size,
+                    found: cells.len(),
+                });
+            }
+            // The first row written is the top one
+            let y = size - 1 - row as u8;
+            for (x, cell) in cells.iter().enumerate() {
+                let position = Position { x: x as u8, y };
+                match *cell {
+                    "." | "0" | "-" => continue,
+                    "x" | "X" => {
+                        blockers.push(position);
+                        continue;
+                    }
+                    _ => {}
+                }
+                let value: u32 = cell
+                    .parse()
+                    .map_err(|_| NotationError::BadValue(cell.to_string()))?;
+                if value > MAX_VALUE {
+                    return Err(NotationError::ValueTooBig(value));
+                }
+                tiles.push((position, value));
+            }
+        }
+        Ok(BoardNotation { size, score, tiles, blockers })
+    }
+}
+
+/// Reads "4x4" or "4x4 score 120".
+fn parse_header(header: &str) -> Option<(u8, u32)> {
+    let mut words = header.split_whitespace();
+    let (width, height) = words.next()?.split_once(['x', 'X'])?;
+    let size: u8 = width.parse().ok()?;
+    if height.parse::<u8>().ok()? != size {
+        return None;
+    }
+    let score = match (words.next(), words.next()) {
+        (None, _) => 0,
+        (Some("score"), Some(score)) => score.parse().ok()?,
+        _ => return None,
+    };
+    words.next().is_none().then_some((size, score))
+}
+
+impl BoardNotation {
+    /// A practice game carrying on from this position.
+    pub fn spec(self, four_chance: f32) -> GameSpec {
+        GameSpec {
+            board_size: self.size,
+            mode: GameMode::Practice,
+            puzzle: None,
+            four_chance,
+            tiles: self.tiles,
+            spawns: Spawns::Seed(u64::from(random::<u32>())),
+            blockers: self.blockers,
+            special_tiles: false,
+            merge_rules: MergeRules::Classic,
+            wraparound: false,
+            score: self.score,
+        }
+    }
+}
+
+/// The position asked for with `--board <file>` on the command line, or
+/// `--board -` to read it from standard input.
+pub fn board_arg() -> Option<GameSpec> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let path = match arg.strip_prefix("--board") {
+            Some("") => args.next()?,
+            Some(path) if path.starts_with('=') => path[1..].to_string(),
+            _ => continue,
+        };
+        let text = if path == "-" {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).map(|_| text)
+        } else {
+            std::fs::read_to_string(&path)
+        };
+        let notation = match text {
+            Ok(text) => text.parse::<BoardNotation>(),
+            Err(error) => {
+                eprintln!("could not read {}: {}", path, error);
+                return None;
+            }
+        };
+        return match notation {
+            Ok(notation) => Some(notation.spec(0.1)),
+            Err(error) => {
+                eprintln!("ignoring board in {}: {}", path, error);
+                None
+            }
+        };
+    }
+    None
+}
+
+/// What happened the last time a position was saved, loaded, copied or
+/// pasted.
+#[derive(Resource, Default)]
+pub struct PositionStatus(pub String);
+
+pub struct NotationPlugin;
+
+impl Plugin for NotationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PositionStatus>()
+            .add_system(handle_position_actions);
+    }
+}
+
+/// Saves or copies the board on screen as text, or loads or pastes one
+/// into the editor.
+#[allow(clippy::too_many_arguments)]
+fn handle_position_actions(
+    mut commands: Commands,
+    mut button_reader: EventReader<ButtonActionEvent>,
+    run_state: Res<State<RunState>>,
+    tiles: Query<(Entity, &Position, &Points)>,
+    blockers: Query<(Entity, &Position), With<Blocker>>,
+    query_board: Query<&Board>,
+    font_spec: Res<FontSpec>,
+    game: Res<Game>,
+    mut sandbox: ResMut<Sandbox>,
+    mut status: ResMut<PositionStatus>,
+) {
+    let path = storage::path(POSITION_FILE);
+    for ButtonActionEvent(action) in button_reader.iter() {
+        let (text, source) = match action {
+            ButtonAction::SavePosition | ButtonAction::CopyPosition => {
+                let board = query_board.single();
+                let notation = BoardNotation {
+                    size: board.size,
+                    score: if *run_state.current() == RunState::Editor {
+                        sandbox.score
+                    } else {
+                        game.score
+                    },
+                    tiles: tiles
+                        .iter()
+                        .map(|(_, position, points)| (*position, points.value))
+                        .collect(),
+                    blockers: blockers.iter().map(|(_, position)| *position).collect(),
+                };
+                let text = notation.to_string();
+                status.0 = if *action == ButtonAction::SavePosition {
+                    match storage::write_text(POSITION_FILE, &text) {
+                        Ok(()) => format!("Saved to {}", path.display()),
+                        Err(error) => format!("Couldn't save {}: {}", path.display(), error),
+                    }
+                } else {
+                    match clipboard::copy(&text) {
+                        Ok(()) => "Copied to the clipboard".to_string(),
+                        Err(error) => format!("Couldn't copy: {}", error),
+                    }
+                };
+                continue;
+            }
+            ButtonAction::LoadPosition => (
+                storage::read_text(POSITION_FILE).map_err(|error| error.to_string()),
+                path.display().to_string(),
+            ),
+            ButtonAction::PastePosition => (clipboard::paste(), "the clipboard".to_string()),
+            _ => continue,
+        };
+        let notation = text.and_then(|text| {
+            text.parse::<BoardNotation>().map_err(|error| error.to_string())
+        });
+        let notation = match notation {
+            Ok(notation) => notation,
+            Err(error) => {
+                status.0 = format!("Couldn't load from {}: {}", source, error);
+                continue;
+            }
+        };
+        let entities = tiles
+            .iter()
+            .map(|(entity, _, _)| entity)
+            .chain(blockers.iter().map(|(entity, _)| entity));
+        for entity in entities {
+            commands.entity(entity).despawn_recursive();
+        }
+        // Placed at the old size for now, the board is rescaled around them
+        // once the new size applies
+        let board = query_board.single();
+        for (position, value) in notation.tiles {
+            spawn_tile(&mut commands, board, &font_spec, position, value, None);
+        }
+        for position in notation.blockers {
+            spawn_blocker(&mut commands, board, position);
+        }
+        sandbox.board_size = notation.size;
+        sandbox.score = notation.score;
+        status.0 = format!("Loaded from {}", source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notation() -> BoardNotation {
+        BoardNotation {
+            size: 4,
+            score: 1024,
+            tiles: vec![
+                (Position { x: 0, y: 3 }, 2),
+                (Position { x: 3, y: 3 }, 4),
+                (Position { x: 1, y: 2 }, 128),
+                (Position { x: 0, y: 0 }, 16),
+            ],
+            blockers: vec![Position { x: 2, y: 2 }],
+        }
+    }
+
+    fn parse(text: &str) -> Result<BoardNotation, NotationError> {
+        text.parse()
+    }
+
+    #[test]
+    fn writes_rows_from_the_top() {
+        let text = notation().to_string();
+        assert_eq!(
+            text,
+            "4x4 score 1024\n  2   .   .   4\n  . 128   x   .\n  .   .   .   .\n 16   .   .   .\n",
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(parse(&notation().to_string()), Ok(notation()));
+    }
+
+    #[test]
+    fn reads_comments_and_other_empty_cells() {
+        let text = "# from the chat\n4x4 score 1024\n2 - 0 4\n. 128 X .\n. . . .\n16 . . .\n";
+        assert_eq!(parse(text), Ok(notation()));
+        assert_eq!(parse("3x3\n. . .\n. . .\n. . .").map(|board| board.score), Ok(0));
+    }
+
+    #[test]
+    fn rejects_bad_boards() {
+        assert_eq!(parse("# nothing\n"), Err(NotationError::MissingHeader));
+        assert_eq!(
+            parse("4x5 score 0"),
+            Err(NotationError::BadHeader("4x5 score 0".to_string())),
+        );
+        assert_eq!(parse("2x2\n. .\n. ."), Err(NotationError::UnsupportedSize(2)));
+        assert_eq!(
+            parse("3x3\n. . .\n. . ."),
+            Err(NotationError::WrongRowCount { expected: 3, found: 2 }),
+        );
+        assert_eq!(
+            parse("3x3\n. . .\n. .\n. . ."),
+            Err(NotationError::WrongRowLength { row: 2, expected: 3, found: 2 }),
+        );
+        assert_eq!(
+            parse("3x3\n. . .\n. a .\n. . ."),
+            Err(NotationError::BadValue("a".to_string())),
+        );
+    }
+
+    #[test]
+    fn rejects_values_too_big() {
+        let too_big = MAX_VALUE * 2;
+        let text = format!("3x3\n. . .\n. {} .\n. . .", too_big);
+        assert_eq!(parse(&text), Err(NotationError::ValueTooBig(too_big)));
+        let text = format!("3x3\n. . .\n. {} .\n. . .", MAX_VALUE);
+        assert!(parse(&text).is_ok());
+    }
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use crate::WINNING_TILE;
+
+/// Which tiles merge, and what they merge into.
+pub trait MergeRule: Send + Sync {
+    /// How many tiles in a row merge at once.
+    fn run_length(&self) -> usize {
+        2
+    }
+
+    /// The value a run of `run_length` tiles merges into, or `None` if they
+    /// can't be merged.
+    fn merge(&self, values: &[u32]) -> Option<u32>;
+
+    /// The value a wildcard stands in for when it's merged with `value`.
+    fn partner(&self, value: u32) -> u32;
+
+    /// The next value up the sequence from `value`.
+    fn step_up(&self, value: u32) -> u32;
+
+    /// Values new tiles start at: the usual one, and the one that turns up
+    /// at the chance set in the settings.
+    fn spawn_values(&self) -> (u32, u32);
+
+    /// Values new tiles are dealt from evenly instead, leaving out
+    /// `spawn_values` and the chance in the settings.
+    fn spawn_deck(&self) -> Option<&'static [u32]> {
+        None
+    }
+
+    /// Whether a move shifts tiles a single cell instead of all the way,
+    /// with new tiles entering from the edge they moved away from. The
+    /// next tile to enter is dealt ahead so it can be shown.
+    fn moves_one_cell(&self) -> bool {
+        false
+    }
+
+    /// The tile a game has to reach to count as won.
+    fn winning_tile(&self) -> u32;
+}
+
+/// Two equal tiles merge into their sum.
+pub struct Classic;
+
+impl MergeRule for Classic {
+    fn merge(&self, values: &[u32]) -> Option<u32> {
+        match values {
+            [first, second] if first == second => Some(first + second),
+            _ => None,
+        }
+    }
+
+    fn partner(&self, value: u32) -> u32 {
+        value
+    }
+
+    fn step_up(&self, value: u32) -> u32 {
+        value * 2
+    }
+
+    fn spawn_values(&self) -> (u32, u32) {
+        (2, 4)
+    }
+
+    fn winning_tile(&self) -> u32 {
+        WINNING_TILE
+    }
+}
+
+/// Neighbouring numbers of the Fibonacci sequence merge into the next one.
+pub struct Fibonacci;
+
+impl Fibonacci {
+    /// The Fibonacci number after `value`, if `value` is one.
+    fn after(value: u32) -> Option<u32> {
+        let (mut current, mut next) = (1u32, 2u32);
+        while current < value {
+            (current, next) = (next, current.checked_add(next)?);
+        }
+        (current == value).then_some(next)
+    }
+}
+
+impl MergeRule for Fibonacci {
+    fn merge(&self, values: &[u32]) -> Option<u32> {
+        let [first, second] = values else {
+            return None;
+        };
+        let (low, high) = (*first.min(second), *first.max(second));
+        let neighbours = (low == 1 && high == 1) || Fibonacci::after(low) == Some(high);
+        neighbours.then_some(low + high)
+    }
+
+    fn partner(&self, value: u32) -> u32 {
+        Fibonacci::after(value).unwrap_or(value)
+    }
+
+    fn step_up(&self, value: u32) -> u32 {
+        Fibonacci::after(value).unwrap_or(value)
+    }
+
+    fn spawn_values(&self) -> (u32, u32) {
+        (1, 2)
+    }
+
+    fn winning_tile(&self) -> u32 {
+        2584
+    }
+}
+
+/// Three equal tiles in a row merge into their sum, so every tile is a power
+/// of three.
+pub struct PowersOfThree;
+
+impl MergeRule for PowersOfThree {
+    fn run_length(&self) -> usize {
+        3
+    }
+
+    fn merge(&self, values: &[u32]) -> Option<u32> {
+        match values {
+            [first, second, third] if first == second && second == third => Some(first * 3),
+            _ => None,
+        }
+    }
+
+    fn partner(&self, value: u32) -> u32 {
+        value
+    }
+
+    fn step_up(&self, value: u32) -> u32 {
+        value * 3
+    }
+
+    fn spawn_values(&self) -> (u32, u32) {
+        (3, 9)
+    }
+
+    fn winning_tile(&self) -> u32 {
+        2187
+    }
+}
+
+/// Ones and twos merge into a three, and from three up equal tiles merge
+/// into their sum, like in Threes. Tiles move one cell a move.
+pub struct Threes;
+
+impl MergeRule for Threes {
+    fn merge(&self, values: &[u32]) -> Option<u32> {
+        match values {
+            [1, 2] | [2, 1] => Some(3),
+            [first, second] if first == second && *first >= 3 => Some(first + second),
+            _ => None,
+        }
+    }
+
+    fn partner(&self, value: u32) -> u32 {
+        match value {
+            1 => 2,
+            2 => 1,
+            value => value,
+        }
+    }
+
+    fn step_up(&self, value: u32) -> u32 {
+        if value < 3 {
+            3
+        } else {
+            value * 2
+        }
+    }
+
+    fn spawn_values(&self) -> (u32, u32) {
+        (1, 2)
+    }
+
+    fn spawn_deck(&self) -> Option<&'static [u32]> {
+        Some(&[1, 2, 3])
+    }
+
+    fn moves_one_cell(&self) -> bool {
+        true
+    }
+
+    fn winning_tile(&self) -> u32 {
+        768
+    }
+}
+
+/// The merge rules a game can be played under.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeRules {
+    #[default]
+    Classic,
+    Fibonacci,
+    PowersOfThree,
+    Threes,
+}
+
+impl MergeRules {
+    pub const ALL: [MergeRules; 4] = [
+        MergeRules::Classic,
+        MergeRules::Fibonacci,
+        MergeRules::PowersOfThree,
+        MergeRules::Threes,
+    ];
+
+    pub fn rule(&self) -> &'static dyn MergeRule {
+        match self {
+            MergeRules::Classic => &Classic,
+            MergeRules::Fibonacci => &Fibonacci,
+            MergeRules::PowersOfThree => &PowersOfThree,
+            MergeRules::Threes => &Threes,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeRules::Classic => "Classic",
+            MergeRules::Fibonacci => "Fibonacci",
+            MergeRules::PowersOfThree => "Powers of 3",
+            MergeRules::Threes => "Threes",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_merges_equal_pairs() {
+        assert_eq!(Classic.merge(&[8, 8]), Some(16));
+        assert_eq!(Classic.merge(&[8, 4]), None);
+        assert_eq!(Classic.merge(&[8]), None);
+    }
+
+    #[test]
+    fn fibonacci_merges_neighbours() {
+        assert_eq!(Fibonacci.merge(&[1, 1]), Some(2));
+        assert_eq!(Fibonacci.merge(&[2, 1]), Some(3));
+        assert_eq!(Fibonacci.merge(&[5, 8]), Some(13));
+        assert_eq!(Fibonacci.merge(&[5, 13]), None);
+        assert_eq!(Fibonacci.merge(&[4, 4]), None);
+        assert_eq!(Fibonacci.partner(8), 13);
+        assert_eq!(Fibonacci.step_up(1597), Fibonacci.winning_tile());
+    }
+
+    #[test]
+    fn powers_of_three_merge_in_threes() {
+        assert_eq!(PowersOfThree.merge(&[9, 9, 9]), Some(27));
+        assert_eq!(PowersOfThree.merge(&[9, 9, 3]), None);
+        assert_eq!(PowersOfThree.merge(&[9, 9]), None);
+    }
+
+    #[test]
+    fn every_rule_can_be_won() {
+        for rules in MergeRules::ALL {
+            let rule = rules.rule();
+            let mut value = rule.spawn_values().0;
+            while value < rule.winning_tile() {
+                value = rule.step_up(value);
+            }
+            assert_eq!(value, rule.winning_tile(), "{}", rules.label());
+        }
+    }
+
+    #[test]
+    fn threes_start_from_one_and_two() {
+        assert_eq!(Threes.merge(&[1, 2]), Some(3));
+        assert_eq!(Threes.merge(&[2, 1]), Some(3));
+        assert_eq!(Threes.merge(&[1, 1]), None);
+        assert_eq!(Threes.merge(&[2, 2]), None);
+        assert_eq!(Threes.merge(&[6, 6]), Some(12));
+        assert_eq!(Threes.partner(1), 2);
+    }
+}
@@ -0,0 +1,398 @@
+use bevy::{prelude::*, window::WindowMode};
+use serde::{Deserialize, Serialize};
+use crate::{
+    storage, Blocker, Board, GameMode, GestureSettings, Materials, MergeRules, Points,
+    TilePlaceholder, TileText, MATERIALS,
+};
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+pub const DARK_MATERIALS: Materials = Materials {
+    background: Color::rgb(0.08, 0.08, 0.1),
+    board: Color::rgb(0.2, 0.2, 0.25),
+    tile_placeholder: Color::rgb(0.25, 0.25, 0.32),
+    tile: Color::rgb(0.45, 0.45, 0.6),
+    tile_text: Color::WHITE,
+    blocker: Color::rgb(0.1, 0.1, 0.13),
+    none: Color::NONE,
+};
+
+pub const SAND_MATERIALS: Materials = Materials {
+    background: Color::rgb(0.98, 0.97, 0.94),
+    board: Color::rgb(0.73, 0.68, 0.63),
+    tile_placeholder: Color::rgb(0.8, 0.75, 0.7),
+    tile: Color::rgb(0.93, 0.89, 0.85),
+    tile_text: Color::rgb(0.47, 0.43, 0.4),
+    blocker: Color::rgb(0.5, 0.45, 0.4),
+    none: Color::NONE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Classic,
+    Dark,
+    Sand,
+}
+
+impl Theme {
+    const ALL: [Theme; 3] = [Theme::Classic, Theme::Dark, Theme::Sand];
+
+    pub fn materials(&self) -> &'static Materials {
+        match self {
+            Theme::Classic => &MATERIALS,
+            Theme::Dark => &DARK_MATERIALS,
+            Theme::Sand => &SAND_MATERIALS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    const ALL: [DisplayMode; 3] = [
+        DisplayMode::Windowed,
+        DisplayMode::Borderless,
+        DisplayMode::Fullscreen,
+    ];
+
+    fn window_mode(&self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+/// A setting shown on the settings screen, which the player can step
+/// through with the arrows next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    GameMode,
+    BoardSize,
+    FourChance,
+    Blockers,
+    SpecialTiles,
+    MergeRules,
+    Wraparound,
+    Theme,
+    AnimationSpeed,
+    SwipeDistance,
+    Volume,
+    SfxVolume,
+    Mute,
+    DisplayMode,
+}
+
+impl SettingKind {
+    pub const ALL: [SettingKind; 14] = [
+        SettingKind::GameMode,
+        SettingKind::BoardSize,
+        SettingKind::FourChance,
+        SettingKind::Blockers,
+        SettingKind::SpecialTiles,
+        SettingKind::MergeRules,
+        SettingKind::Wraparound,
+        SettingKind::Theme,
+        SettingKind::AnimationSpeed,
+        SettingKind::SwipeDistance,
+        SettingKind::Volume,
+        SettingKind::SfxVolume,
+        SettingKind::Mute,
+        SettingKind::DisplayMode,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SettingKind::GameMode => "Mode",
+            SettingKind::BoardSize => "Board size",
+            SettingKind::FourChance => "Chance of a 4",
+            SettingKind::Blockers => "Blockers",
+            SettingKind::SpecialTiles => "Special tiles",
+            SettingKind::MergeRules => "Numbers",
+            SettingKind::Wraparound => "Wraparound",
+            SettingKind::Theme => "Theme",
+            SettingKind::AnimationSpeed => "Animation speed",
+            SettingKind::SwipeDistance => "Swipe distance",
+            SettingKind::Volume => "Master volume",
+            SettingKind::SfxVolume => "Effects volume",
+            SettingKind::Mute => "Sound",
+            SettingKind::DisplayMode => "Window",
+        }
+    }
+}
+
+pub const BOARD_SIZES: [u8; 6] = [3, 4, 5, 6, 7, 8];
+const FOUR_CHANCES: [f32; 6] = [0.0, 0.1, 0.2, 0.3, 0.4, 0.5];
+const BLOCKER_COUNTS: [u8; 5] = [0, 1, 2, 3, 4];
+const ANIMATION_SPEEDS: [f32; 5] = [0.5, 1.0, 1.5, 2.0, 4.0];
+const SWIPE_DISTANCES: [f32; 5] = [25.0, 35.0, 50.0, 75.0, 100.0];
+const VOLUMES: [f32; 11] = [0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0];
+
+/// The option closest to `value`.
+fn nearest(options: &[f32], value: f32) -> f32 {
+    options
+        .iter()
+        .copied()
+        .min_by(|a, b| (a - value).abs().total_cmp(&(b - value).abs()))
+        .unwrap_or(value)
+}
+
+/// Moves `step` places through `options` from `current`, staying within
+/// the list. Values that aren't in the list start from the first option.
+pub fn step_through<T: Copy + PartialEq>(options: &[T], current: T, step: i32) -> T {
+    let index = options
+        .iter()
+        .position(|option| *option == current)
+        .unwrap_or(0) as i32;
+    let last = options.len() as i32 - 1;
+    options[(index + step).clamp(0, last) as usize]
+}
+
+/// Player preferences, saved to the config file whenever they change.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Rules for the next game.
+    pub mode: GameMode,
+    /// Width and height of the board, used from the next game on.
+    pub board_size: u8,
+    /// Chance that a newly spawned tile is a 4 rather than a 2.
+    pub four_chance: f32,
+    /// Cells blocked off at random at the start of each game.
+    pub blockers: u8,
+    /// Whether wildcards, bombs and doublers turn up among the new tiles.
+    pub special_tiles: bool,
+    /// Which tiles merge, for the next game on.
+    pub merge_rules: MergeRules,
+    /// Whether the edges of the board join up, from the next game on.
+    pub wraparound: bool,
+    pub theme: Theme,
+    /// Multiplier on how fast tiles slide, higher is faster.
+    pub animation_speed: f32,
+    /// How far a drag or swipe has to go to make a move, in logical pixels.
+    /// Shorter is more sensitive.
+    pub swipe_distance: f32,
+    pub volume: f32,
+    /// Volume of sound effects, relative to `volume`.
+    pub sfx_volume: f32,
+    pub muted: bool,
+    pub display_mode: DisplayMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            mode: GameMode::Classic,
+            board_size: 4,
+            four_chance: 0.1,
+            blockers: 0,
+            special_tiles: false,
+            merge_rules: MergeRules::Classic,
+            wraparound: false,
+            theme: Theme::Classic,
+            animation_speed: 1.0,
+            swipe_distance: 50.0,
+            volume: 0.8,
+            sfx_volume: 1.0,
+            muted: false,
+            display_mode: DisplayMode::Windowed,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        storage::load::<Settings>(SETTINGS_FILE)
+            .unwrap_or_default()
+            .within_limits()
+    }
+
+    /// These settings with every value moved onto its nearest allowed
+    /// option, since the config file may have been edited by hand.
+    fn within_limits(mut self) -> Self {
+        // Daily games are only started from the daily challenge screen
+        if !GameMode::ALL.contains(&self.mode) {
+            self.mode = GameMode::Classic;
+        }
+        self.board_size = self
+            .board_size
+            .clamp(BOARD_SIZES[0], BOARD_SIZES[BOARD_SIZES.len() - 1]);
+        self.four_chance = nearest(&FOUR_CHANCES, self.four_chance);
+        self.blockers = self
+            .blockers
+            .min(BLOCKER_COUNTS[BLOCKER_COUNTS.len() - 1]);
+        self.animation_speed = nearest(&ANIMATION_SPEEDS, self.animation_speed);
+        self.swipe_distance = nearest(&SWIPE_DISTANCES, self.swipe_distance);
+        self.volume = nearest(&VOLUMES, self.volume);
+        self.sfx_volume = nearest(&VOLUMES, self.sfx_volume);
+        self
+    }
+
+    pub fn save(&self) {
+        storage::save(SETTINGS_FILE, self);
+    }
+
+    pub fn adjust(&mut self, kind: SettingKind, step: i32) {
+        match kind {
+            SettingKind::GameMode => {
+                self.mode = step_through(&GameMode::ALL, self.mode, step);
+            }
+            SettingKind::BoardSize => {
+                self.board_size = step_through(&BOARD_SIZES, self.board_size, step);
+            }
+            SettingKind::FourChance => {
+                self.four_chance = step_through(&FOUR_CHANCES, self.four_chance, step);
+            }
+            SettingKind::Blockers => {
+                self.blockers = step_through(&BLOCKER_COUNTS, self.blockers, step);
+            }
+            SettingKind::SpecialTiles => {
+                self.special_tiles = step_through(&[false, true], self.special_tiles, step);
+            }
+            SettingKind::MergeRules => {
+                self.merge_rules = step_through(&MergeRules::ALL, self.merge_rules, step);
+            }
+            SettingKind::Wraparound => {
+                self.wraparound = step_through(&[false, true], self.wraparound, step);
+            }
+            SettingKind::Theme => {
+                self.theme = step_through(&Theme::ALL, self.theme, step);
+            }
+            SettingKind::AnimationSpeed => {
+                self.animation_speed = step_through(&ANIMATION_SPEEDS, self.animation_speed, step);
+            }
+            SettingKind::SwipeDistance => {
+                self.swipe_distance = step_through(&SWIPE_DISTANCES, self.swipe_distance, step);
+            }
+            SettingKind::Volume => {
+                self.volume = step_through(&VOLUMES, self.volume, step);
+            }
+            SettingKind::SfxVolume => {
+                self.sfx_volume = step_through(&VOLUMES, self.sfx_volume, step);
+            }
+            SettingKind::Mute => {
+                self.muted = step_through(&[false, true], self.muted, step);
+            }
+            SettingKind::DisplayMode => {
+                self.display_mode = step_through(&DisplayMode::ALL, self.display_mode, step);
+            }
+        }
+    }
+
+    pub fn display(&self, kind: SettingKind) -> String {
+        match kind {
+            SettingKind::GameMode => self.mode.label().to_string(),
+            SettingKind::BoardSize => format!("{0}x{0}", self.board_size),
+            SettingKind::FourChance => format!("{:.0}%", self.four_chance * 100.0),
+            SettingKind::Blockers => match self.blockers {
+                0 => "None".to_string(),
+                count => count.to_string(),
+            },
+            SettingKind::SpecialTiles => if self.special_tiles { "On" } else { "Off" }.to_string(),
+            SettingKind::MergeRules => self.merge_rules.label().to_string(),
+            SettingKind::Wraparound => if self.wraparound { "On" } else { "Off" }.to_string(),
+            SettingKind::Theme => format!("{:?}", self.theme),
+            SettingKind::AnimationSpeed => format!("{}x", self.animation_speed),
+            SettingKind::SwipeDistance => format!("{} px", self.swipe_distance),
+            SettingKind::Volume => format!("{:.0}%", self.volume * 100.0),
+            SettingKind::SfxVolume => format!("{:.0}%", self.sfx_volume * 100.0),
+            SettingKind::Mute => if self.muted { "Muted" } else { "On" }.to_string(),
+            SettingKind::DisplayMode => format!("{:?}", self.display_mode),
+        }
+    }
+
+    /// Volume sound effects play at, taking the master volume and mute
+    /// into account.
+    pub fn effects_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume * self.sfx_volume
+        }
+    }
+
+    /// How long a tile takes to slide to its new cell.
+    pub fn slide_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f32(0.2 / self.animation_speed.max(0.1))
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Settings::load())
+            .add_system(apply_display_mode)
+            .add_system(apply_swipe_distance)
+            // After the frame's tiles have been spawned, so they never show
+            // up in the wrong colours
+            .add_system_to_stage(CoreStage::PostUpdate, apply_theme);
+    }
+}
+
+fn apply_swipe_distance(
+    settings: Res<Settings>,
+    mut gestures: ResMut<GestureSettings>,
+) {
+    if settings.is_changed() {
+        gestures.min_distance = settings.swipe_distance;
+    }
+}
+
+fn apply_display_mode(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = settings.display_mode.window_mode();
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_theme(
+    settings: Res<Settings>,
+    mut clear_color: ResMut<ClearColor>,
+    new_tiles: Query<(), Or<(Added<Points>, Added<TilePlaceholder>, Added<Blocker>)>>,
+    mut sprites: ParamSet<(
+        Query<&mut Sprite, With<Board>>,
+        Query<&mut Sprite, With<TilePlaceholder>>,
+        Query<&mut Sprite, With<Points>>,
+        Query<&mut Sprite, With<Blocker>>,
+    )>,
+    mut texts: Query<&mut Text, With<TileText>>,
+) {
+    if !settings.is_changed() && new_tiles.is_empty() {
+        return;
+    }
+    let materials = settings.theme.materials();
+
+    clear_color.0 = materials.background;
+    for mut sprite in sprites.p0().iter_mut() {
+        sprite.color = materials.board;
+    }
+    for mut sprite in sprites.p1().iter_mut() {
+        sprite.color = materials.tile_placeholder;
+    }
+    for mut sprite in sprites.p2().iter_mut() {
+        sprite.color = materials.tile;
+    }
+    for mut sprite in sprites.p3().iter_mut() {
+        sprite.color = materials.blocker;
+    }
+    for mut text in texts.iter_mut() {
+        text.sections[0].style.color = materials.tile_text;
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use crate::{Game, MergeEvent, NewTileEvent, RunState, Settings, TileSpawnedEvent};
+
+/// Sound effects, loaded alongside the font when the game starts.
+#[derive(Resource)]
+pub struct SoundEffects {
+    slide: Handle<AudioSource>,
+    merge: Handle<AudioSource>,
+    spawn: Handle<AudioSource>,
+    win: Handle<AudioSource>,
+    game_over: Handle<AudioSource>,
+}
+
+impl FromWorld for SoundEffects {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world
+            .get_resource_mut::<AssetServer>()
+            .unwrap();
+        SoundEffects {
+            slide: asset_server.load("sounds/slide.ogg"),
+            merge: asset_server.load("sounds/merge.ogg"),
+            spawn: asset_server.load("sounds/spawn.ogg"),
+            win: asset_server.load("sounds/win.ogg"),
+            game_over: asset_server.load("sounds/game_over.ogg"),
+        }
+    }
+}
+
+/// Playback speed of the merge sound for a tile, raising the pitch by a
+/// whole tone each time the value doubles, from a 4 upwards.
+fn merge_pitch(value: u32) -> f32 {
+    let doublings = value.max(4).ilog2() as f32 - 2.0;
+    2f32.powf(doublings * 2.0 / 12.0)
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SoundEffects>()
+            .add_system(play_move_sounds)
+            .add_system_set(
+                SystemSet::on_enter(RunState::GameOver)
+                    .with_system(play_game_over_sound)
+            );
+    }
+}
+
+fn play_move_sounds(
+    mut tile_reader: EventReader<NewTileEvent>,
+    mut spawned_reader: EventReader<TileSpawnedEvent>,
+    mut merge_reader: EventReader<MergeEvent>,
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    game: Res<Game>,
+    audio: Res<Audio>,
+) {
+    let volume = settings.effects_volume();
+    let play = |sound: &Handle<AudioSource>, speed: f32| {
+        if volume > 0.0 {
+            audio.play_with_settings(
+                sound.clone(),
+                PlaybackSettings::ONCE.with_volume(volume).with_speed(speed),
+            );
+        }
+    };
+
+    if tile_reader.iter().count() > 0 {
+        play(&sounds.slide, 1.0);
+    }
+    if spawned_reader.iter().count() > 0 {
+        play(&sounds.spawn, 1.0);
+    }
+    // A single merge sound per move, pitched for the biggest tile made
+    if let Some(value) = merge_reader.iter().map(|merge| merge.value).max() {
+        if value == game.merge_rules.rule().winning_tile() {
+            play(&sounds.win, 1.0);
+        } else {
+            play(&sounds.merge, merge_pitch(value));
+        }
+    }
+}
+
+fn play_game_over_sound(
+    sounds: Res<SoundEffects>,
+    settings: Res<Settings>,
+    audio: Res<Audio>,
+) {
+    let volume = settings.effects_volume();
+    if volume > 0.0 {
+        audio.play_with_settings(
+            sounds.game_over.clone(),
+            PlaybackSettings::ONCE.with_volume(volume),
+        );
+    }
+}
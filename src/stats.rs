@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::{storage, GameFinishedEvent, GameMode};
+
+const STATS_FILE: &str = "stats.ron";
+
+/// Lifetime statistics across every game played on this machine.
+#[derive(Resource, Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Statistics {
+    pub games_played: u32,
+    pub wins: u32,
+    pub highest_tile: u32,
+    pub total_merges: u64,
+    pub total_score: u64,
+    pub longest_game_moves: u32,
+    /// Seconds of play in the longest game.
+    pub longest_game_duration: f32,
+    /// Best score for each board size played, leaving out practice games.
+    pub best_scores: BTreeMap<u8, u32>,
+    /// How many games scored within each bucket, keyed by the bucket's
+    /// lower bound (see [`score_bucket`]).
+    pub score_distribution: BTreeMap<u32, u32>,
+    /// How many games ended with each value as their biggest tile.
+    pub max_tile_distribution: BTreeMap<u32, u32>,
+}
+
+/// Groups scores into buckets that double in size, so the distribution stays
+/// readable from first games to very long ones: 0, 1000, 2000, 4000...
+pub fn score_bucket(score: u32) -> u32 {
+    if score < 1000 {
+        0
+    } else {
+        let mut bucket = 1000;
+        while bucket * 2 <= score {
+            bucket *= 2;
+        }
+        bucket
+    }
+}
+
+impl Statistics {
+    pub fn load() -> Self {
+        storage::load(STATS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(STATS_FILE, self);
+    }
+
+    pub fn win_rate(&self) -> f32 {
+        if self.games_played == 0 {
+            0.0
+        } else {
+            self.wins as f32 / self.games_played as f32
+        }
+    }
+
+    pub fn average_score(&self) -> u64 {
+        if self.games_played == 0 {
+            0
+        } else {
+            self.total_score / u64::from(self.games_played)
+        }
+    }
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Statistics::load())
+            .add_system(update_statistics);
+    }
+}
+
+fn update_statistics(
+    mut finished_reader: EventReader<GameFinishedEvent>,
+    mut stats: ResMut<Statistics>,
+) {
+    let mut changed = false;
+    for GameFinishedEvent(record) in finished_reader.iter() {
+        stats.games_played += 1;
+        if record.won() {
+            stats.wins += 1;
+        }
+        stats.highest_tile = stats.highest_tile.max(record.max_tile);
+        stats.total_merges += u64::from(record.merges);
+        stats.total_score += u64::from(record.score);
+        stats.longest_game_moves = stats.longest_game_moves.max(record.moves);
+        stats.longest_game_duration = stats.longest_game_duration.max(record.duration);
+
+        // Practice games get as much help as the player wants
+        if record.mode != GameMode::Practice {
+            let best = stats.best_scores.entry(record.board_size).or_insert(0);
+            *best = (*best).max(record.score);
+        }
+        *stats
+            .score_distribution
+            .entry(score_bucket(record.score))
+            .or_insert(0) += 1;
+        *stats
+            .max_tile_distribution
+            .entry(record.max_tile)
+            .or_insert(0) += 1;
+        changed = true;
+    }
+    if changed {
+        stats.save();
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::PathBuf};
+
+/// Directory the game keeps its config and save files in, following the
+/// XDG convention where it's available and the working directory otherwise.
+fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("boxes");
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        return PathBuf::from(home).join(".config").join("boxes");
+    }
+    PathBuf::from(".")
+}
+
+/// Reads `file_name` from the data directory as RON. A missing file is not
+/// an error, since nothing has been saved yet on a fresh install.
+pub fn load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let path = data_dir().join(file_name);
+    let contents = fs::read_to_string(&path).ok()?;
+    match ron::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("ignoring unreadable {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(file_name: &str, value: &T) {
+    let dir = data_dir();
+    let contents = match ron::ser::to_string_pretty(
+        value,
+        ron::ser::PrettyConfig::default(),
+    ) {
+        Ok(contents) => contents,
+        Err(error) => {
+            error!("could not serialize {}: {}", file_name, error);
+            return;
+        }
+    };
+    if let Err(error) = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(file_name), contents))
+    {
+        error!("could not save {}: {}", file_name, error);
+    }
+}
+
+/// Path of `file_name` in the data directory, for files the player reads or
+/// edits by hand.
+pub fn path(file_name: &str) -> PathBuf {
+    data_dir().join(file_name)
+}
+
+pub fn read_text(file_name: &str) -> io::Result<String> {
+    fs::read_to_string(path(file_name))
+}
+
+pub fn write_text(file_name: &str, contents: &str) -> io::Result<()> {
+    fs::create_dir_all(data_dir())?;
+    fs::write(path(file_name), contents)
+}
@@ -1,14 +1,330 @@
 use bevy::prelude::*;
 use bevy::prelude::BackgroundColor;
-use crate::{FontSpec, Game, RunState, MATERIALS};
+use bevy::ecs::system::EntityCommands;
+use bevy::app::AppExit;
+use crate::{
+    challenge_date, daily_spec, date::Date, decode, encode, key_name, step_through,
+    Action, Blocker, Board, CodeEntry, CustomGame, DailyHistory, FontSpec, Game,
+    GameMode, GameSpec, Hint, KeyBindings, Leaderboards, LevelPack, LevelProgress,
+    Levels, MergeEvent, NameEntry, Points, Position, PositionStatus, RunState, Sandbox,
+    SettingKind, Settings, Statistics, TileSpawner, BOARD_SIZES, LEADERBOARD_SIZE, MATERIALS,
+};
 
 
+/// The full-window node holding the header, restyled by the layout
+/// systems when the window changes shape.
+#[derive(Component)]
+pub struct HudRoot;
+
 #[derive(Component)]
 pub struct ScoreDisplay;
 
 #[derive(Component)]
 pub struct BestScoreDisplay;
 
+#[derive(Component)]
+pub struct HintDisplay;
+
+#[derive(Component)]
+pub struct ModeDisplay;
+
+/// The countdown next to the scores, only shown in timed games.
+#[derive(Component)]
+pub struct TimeBox;
+
+#[derive(Component)]
+pub struct TimeDisplay;
+
+/// Moves left in a puzzle, only shown in puzzle games.
+#[derive(Component)]
+pub struct MovesBox;
+
+#[derive(Component)]
+pub struct MovesDisplay;
+
+/// The next tile to enter, only shown under rules that deal it ahead.
+#[derive(Component)]
+pub struct NextTileBox;
+
+#[derive(Component)]
+pub struct NextTileDisplay;
+
+/// The countdown flashes for this many seconds before time runs out.
+const TIME_WARNING: f32 = 10.0;
+
+/// What a button does when clicked.
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum ButtonAction {
+    /// Ends the current game, or starts a new one once it's over.
+    ToggleGame,
+    /// Starts a new game, checking first if one is already in progress.
+    NewGame,
+    /// Starts the game in `PendingGame` straight away.
+    StartGame,
+    OpenLevels,
+    /// Starts a level, checking first if a game is already in progress.
+    PlayLevel(usize),
+    OpenDaily,
+    /// Starts today's daily challenge, checking first if a game is already
+    /// in progress.
+    PlayDaily,
+    /// Steps the daily challenge calendar through the months.
+    DailyMonth(i32),
+    /// Shows the code for the game in progress.
+    OpenShare,
+    OpenCodeEntry,
+    /// Starts the game from the code typed in, checking first if a game is
+    /// already in progress.
+    PlayCode,
+    /// Puts the code for the game in progress on the clipboard.
+    CopyCode,
+    /// Replaces the code typed in with the one on the clipboard.
+    PasteCode,
+    /// Opens the board editor, checking first if a game is in progress.
+    OpenEditor,
+    StartEditor,
+    /// Steps the editor's board through the board sizes.
+    SandboxSize(i32),
+    ClearBoard,
+    /// Starts a game from the tiles laid out in the editor.
+    PlayLayout,
+    /// Writes the board out as text to the position file.
+    SavePosition,
+    /// Lays out the board in the position file in the editor.
+    LoadPosition,
+    /// Puts the board on the clipboard as text.
+    CopyPosition,
+    /// Lays out the board on the clipboard in the editor.
+    PastePosition,
+    EndGame,
+    RestartGame,
+    /// Returns to the game left for the main menu.
+    Continue,
+    Pause,
+    MainMenu,
+    Quit,
+    OpenSettings,
+    /// Steps a setting forwards or backwards through its options.
+    AdjustSetting(SettingKind, i32),
+    OpenBindings,
+    OpenStats,
+    OpenLeaderboard,
+    /// Steps the leaderboard screen through the board sizes and modes.
+    LeaderboardPage(i32),
+    /// Puts the score from the game just ended on the leaderboard.
+    SubmitScore,
+    SkipScore,
+    CloseScreen,
+    /// Closes the confirmation dialog, running the action it was asked for.
+    Confirm,
+    AddBinding(Action),
+    ClearBinding(Action),
+    ResetBindings,
+}
+
+/// Sent when a button is clicked, or activated from the keyboard or a gamepad.
+pub struct ButtonActionEvent(pub ButtonAction);
+
+/// Device-independent input for moving between buttons on a screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuInput {
+    Previous,
+    Next,
+    Activate,
+    Back,
+    /// Pauses the game, or resumes it from the pause screen.
+    Menu,
+}
+
+pub struct MenuInputEvent(pub MenuInput);
+
+/// The button that keyboard and gamepad input currently points at.
+#[derive(Default, Resource)]
+pub struct ButtonFocus(Option<Entity>);
+
+/// Root node of a screen drawn over the game. Only the buttons of the
+/// highest `layer` screen can be reached with menu navigation.
+#[derive(Component)]
+pub struct Screen {
+    layer: i32,
+}
+
+#[derive(Component)]
+pub struct MainMenuScreen;
+
+#[derive(Component)]
+pub struct PauseScreen;
+
+#[derive(Component)]
+pub struct SettingsScreen;
+
+/// The current value of a setting on the settings screen.
+#[derive(Component)]
+pub struct SettingValueText(SettingKind);
+
+#[derive(Component)]
+pub struct BindingsScreen;
+
+#[derive(Component)]
+pub struct StatsScreen;
+
+#[derive(Component)]
+pub struct LeaderboardScreen;
+
+#[derive(Component)]
+pub struct LevelSelectScreen;
+
+#[derive(Component)]
+pub struct DailyScreen;
+
+#[derive(Component)]
+pub struct CalendarTitleText;
+
+/// One day's square on the daily challenge calendar, numbered from the
+/// top left. The month's first day falls under its weekday.
+#[derive(Component)]
+pub struct CalendarCell(usize);
+
+#[derive(Component)]
+pub struct ShareScreen;
+
+#[derive(Component)]
+pub struct CodeEntryScreen;
+
+#[derive(Component)]
+pub struct CodeEntryText;
+
+#[derive(Component)]
+pub struct CodeErrorText;
+
+/// Whether the last copy or paste of a code worked.
+#[derive(Component)]
+pub struct CodeStatusText;
+
+#[derive(Component)]
+pub struct EditorScreen;
+
+#[derive(Component)]
+pub struct SandboxSizeText;
+
+#[derive(Component)]
+pub struct PositionStatusText;
+
+/// Which month the daily challenge calendar is showing.
+#[derive(Default, Resource)]
+pub struct CalendarView {
+    year: i32,
+    month: u8,
+}
+
+/// Setup for the game about to start, waiting on the player agreeing to
+/// abandon the one in progress. `None` sets it up from the settings.
+#[derive(Default, Resource)]
+pub struct PendingGame(Option<GameSpec>);
+
+#[derive(Component)]
+pub struct LeaderboardTitleText;
+
+/// One cell of the leaderboard screen's table.
+#[derive(Component)]
+pub struct LeaderboardCell {
+    row: usize,
+    column: usize,
+}
+
+/// Which leaderboard the leaderboard screen is showing.
+#[derive(Default, Resource)]
+pub struct LeaderboardView {
+    board_size: u8,
+    mode: GameMode,
+}
+
+/// Prompt for the player's name, shown over the board when a game ends
+/// with a high score.
+#[derive(Component)]
+pub struct NameEntryScreen;
+
+#[derive(Component)]
+pub struct NameEntryText;
+
+#[derive(Component)]
+pub struct ConfirmScreen;
+
+/// The question asked by the confirmation dialog and what happens if the
+/// player agrees. The action is only sent once the dialog has closed, so it
+/// applies to the screen the dialog was opened from.
+#[derive(Default, Resource)]
+pub struct Confirmation {
+    message: String,
+    on_confirm: Option<ButtonAction>,
+    confirmed: bool,
+}
+
+impl Confirmation {
+    /// Opens the confirmation dialog over the current screen.
+    pub fn ask(
+        &mut self,
+        run_state: &mut State<RunState>,
+        message: &str,
+        on_confirm: ButtonAction,
+    ) {
+        if run_state.push(RunState::Confirm).is_ok() {
+            self.message = message.to_string();
+            self.on_confirm = Some(on_confirm);
+            self.confirmed = false;
+        }
+    }
+}
+
+/// The list of keys bound to an action on the bindings screen.
+#[derive(Component)]
+pub struct BindingKeysText(Action);
+
+#[derive(Component)]
+pub struct BindingStatusText;
+
+/// Tracks the action waiting for a key on the bindings screen and the
+/// feedback shown to the player about their last attempt.
+#[derive(Default, Resource)]
+pub struct Rebinding {
+    waiting: Option<Action>,
+    message: String,
+}
+
+/// Marks the scorebox node so "+N" popups can be attached to it.
+#[derive(Component)]
+pub struct ScoreBox;
+
+/// A floating "+N" label that rises and fades out before despawning.
+/// `rise` is the distance travelled over the popup's lifetime.
+#[derive(Component)]
+pub struct ScorePopup {
+    timer: Timer,
+    start: f32,
+    rise: f32,
+}
+
+impl ScorePopup {
+    fn new(start: f32, rise: f32) -> Self {
+        ScorePopup {
+            timer: Timer::from_seconds(0.8, TimerMode::Once),
+            start,
+            rise,
+        }
+    }
+}
+
+/// The score currently shown in the scorebox, which counts up towards
+/// `Game::score` rather than jumping straight to it.
+#[derive(Default, Resource)]
+pub struct ScoreCounter {
+    displayed: f32,
+}
+
+/// How quickly the score counter closes the gap to the real score, as a
+/// fraction of the remaining difference per second.
+const SCORE_COUNT_RATE: f32 = 8.0;
+
 #[derive(Resource)]
 pub struct ButtonMaterials {
     normal: Color,
@@ -28,10 +344,158 @@ pub struct GameUiPlugin;
 impl Plugin for GameUiPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<ScoreCounter>()
+            .init_resource::<Rebinding>()
+            .init_resource::<ButtonFocus>()
+            .init_resource::<Confirmation>()
+            .init_resource::<LeaderboardView>()
+            .init_resource::<PendingGame>()
+            .init_resource::<CalendarView>()
+            .add_event::<ButtonActionEvent>()
+            .add_event::<MenuInputEvent>()
             .add_startup_system(setup_ui)
             .add_system(scoreboard)
+            .add_system(hint_text)
+            .add_system(mode_text)
+            .add_system(countdown)
+            .add_system(moves_left_text)
+            .add_system(next_tile_text)
+            .add_system(spawn_score_popups)
+            .add_system(animate_ui_popups)
+            .add_system(animate_tile_popups)
             .add_system(button_interaction_system)
-            .add_system(button_text_system);
+            .add_system(queue_game.before(handle_button_actions))
+            .add_system(handle_button_actions.after(rebind_keys))
+            .add_system(keyboard_menu_input)
+            .add_system(menu_focus)
+            .add_system(focus_highlight.after(menu_focus))
+            .add_system(button_text_system)
+            .add_system(name_entry_screen)
+            .add_system(name_entry_text)
+            .add_system_set(
+                SystemSet::on_enter(RunState::MainMenu)
+                    .with_system(setup_main_menu)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::MainMenu)
+                    .with_system(despawn_screen::<MainMenuScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Paused)
+                    .with_system(setup_pause_screen)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Paused)
+                    .with_system(despawn_screen::<PauseScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Settings)
+                    .with_system(setup_settings_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Settings)
+                    .with_system(settings_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Settings)
+                    .with_system(despawn_screen::<SettingsScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Confirm)
+                    .with_system(setup_confirm_screen)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Confirm)
+                    .with_system(despawn_screen::<ConfirmScreen>)
+                    .with_system(send_confirmed_action)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Bindings)
+                    .with_system(setup_bindings_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Bindings)
+                    .with_system(rebind_keys)
+                    .with_system(bindings_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Bindings)
+                    .with_system(despawn_screen::<BindingsScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Stats)
+                    .with_system(setup_stats_screen)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Stats)
+                    .with_system(despawn_screen::<StatsScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Leaderboard)
+                    .with_system(setup_leaderboard_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Leaderboard)
+                    .with_system(leaderboard_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Leaderboard)
+                    .with_system(despawn_screen::<LeaderboardScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::LevelSelect)
+                    .with_system(setup_level_select_screen)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::LevelSelect)
+                    .with_system(despawn_screen::<LevelSelectScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Daily)
+                    .with_system(setup_daily_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Daily)
+                    .with_system(calendar_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Daily)
+                    .with_system(despawn_screen::<DailyScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Share)
+                    .with_system(setup_share_screen)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Share)
+                    .with_system(despawn_screen::<ShareScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::EnterCode)
+                    .with_system(setup_code_entry_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::EnterCode)
+                    .with_system(code_entry_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::EnterCode)
+                    .with_system(despawn_screen::<CodeEntryScreen>)
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Editor)
+                    .with_system(setup_editor_screen)
+            )
+            .add_system_set(
+                SystemSet::on_update(RunState::Editor)
+                    .with_system(sandbox_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(RunState::Editor)
+                    .with_system(despawn_screen::<EditorScreen>)
+            )
+            .add_system(position_status_text)
+            .add_system(code_status_text);
     }
 }
 
@@ -50,6 +514,7 @@ fn setup_ui(
         background_color: BackgroundColor(MATERIALS.none),
         ..Default::default()
     })
+    .insert(HudRoot)
     .with_children(|parent| {
         parent.spawn(TextBundle {
             text: Text::from_section(
@@ -64,6 +529,8 @@ fn setup_ui(
             ),
             ..Default::default()
         });
+        spawn_text(parent, &font_spec, "", 20.0)
+            .insert(ModeDisplay);
 
         parent
             .spawn(NodeBundle {
@@ -94,6 +561,7 @@ fn setup_ui(
                         background_color: BackgroundColor(MATERIALS.tile_placeholder),
                         ..Default::default()
                     })
+                    .insert(ScoreBox)
                     .with_children(|parent| {
                         parent.spawn(TextBundle {
                             text: Text::from_section(
@@ -179,74 +647,355 @@ fn setup_ui(
                             .insert(BestScoreDisplay);
                     });
                 // end best scorebox
+                spawn_counter_box(parent, &font_spec, "Time", TimeBox, TimeDisplay);
+                spawn_counter_box(parent, &font_spec, "Moves", MovesBox, MovesDisplay);
+                spawn_counter_box(parent, &font_spec, "Next", NextTileBox, NextTileDisplay);
             });
+        spawn_button(parent, &font_spec, "Button", ButtonAction::ToggleGame);
+        spawn_button(parent, &font_spec, "Menu", ButtonAction::Pause);
         parent
-            .spawn(ButtonBundle {
+            .spawn(TextBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ),
                 style: Style {
-                    size: Size::new(Val::Px(100.0), Val::Px(30.0)),
-                    justify_content: JustifyContent::Center,
-                    align_items: AlignItems::Center,
+                    margin: UiRect::all(Val::Px(5.0)),
                     ..Default::default()
                 },
                 ..Default::default()
             })
-            .with_children(|parent| {
-                parent.spawn(TextBundle {
+            .insert(HintDisplay);
+    });
+}
+
+/// A box next to the scores for a counter only some modes have, hidden
+/// until the mode needs it.
+fn spawn_counter_box(
+    parent: &mut ChildBuilder,
+    font_spec: &FontSpec,
+    label: &str,
+    box_marker: impl Component,
+    text_marker: impl Component,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::None,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                margin: UiRect::horizontal(Val::Px(20.0)),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            background_color: BackgroundColor(MATERIALS.tile_placeholder),
+            ..Default::default()
+        })
+        .insert(box_marker)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 15.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ..Default::default()
+            });
+            parent
+                .spawn(TextBundle {
                     text: Text::from_section(
-                        "Button",
+                        "",
                         TextStyle {
                             font: font_spec.family.clone(),
                             font_size: 20.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        }
+                            color: Color::WHITE,
+                        },
                     ),
                     ..Default::default()
-                });
+                })
+                .insert(text_marker);
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    font_spec: &FontSpec,
+    label: &str,
+    action: ButtonAction,
+) -> Entity {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(120.0), Val::Px(30.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                margin: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            },
+            background_color: BUTTON_MATERIALS.normal.into(),
+            ..Default::default()
+        })
+        .insert(action)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    }
+                ),
+                ..Default::default()
             });
-    });
+        })
+        .id()
 }
 
 fn scoreboard(
     game: Res<Game>,
+    time: Res<Time>,
+    mut counter: ResMut<ScoreCounter>,
     mut query_scores: ParamSet<(
         Query<&mut Text, With<ScoreDisplay>>,
         Query<&mut Text, With<BestScoreDisplay>>,
     )>
 ) {
+    let target = game.score as f32;
+    if target < counter.displayed {
+        // The game was reset, there's nothing to count up to.
+        counter.displayed = target;
+    } else if target > counter.displayed {
+        let step = (target - counter.displayed)
+            * (SCORE_COUNT_RATE * time.delta_seconds()).min(1.0);
+        counter.displayed = (counter.displayed + step.max(1.0)).min(target);
+    }
+
     let mut binding = query_scores.p0();
     let mut text = binding.single_mut();
-    text.sections[0].value = game.score.to_string();
+    text.sections[0].value = (counter.displayed as u32).to_string();
 
     let mut binding = query_scores.p1();
     let mut text = binding.single_mut();
     text.sections[0].value = game.score_best.to_string();
 }
 
+fn spawn_score_popups(
+    mut commands: Commands,
+    mut merge_reader: EventReader<MergeEvent>,
+    font_spec: Res<FontSpec>,
+    query_board: Query<&Board>,
+    score_box: Query<Entity, With<ScoreBox>>,
+) {
+    let board = query_board.single();
+    let mut total = 0;
+
+    for event in merge_reader.iter() {
+        total += event.value;
+
+        // A smaller popup rising from the merged tile itself
+        let x = board.cell_position_to_physical(event.position.x);
+        let y = board.cell_position_to_physical(event.position.y);
+        commands
+            .spawn(Text2dBundle {
+                text: Text::from_section(
+                    format!("+{}", event.value),
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ).with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_xyz(x, y, 10.0),
+                ..Default::default()
+            })
+            .insert(ScorePopup::new(y, 30.0));
+    }
+
+    // One popup per move on the scorebox, summing every merge in it
+    if total > 0 {
+        commands
+            .entity(score_box.single())
+            .with_children(|parent| {
+                parent
+                    .spawn(TextBundle {
+                        text: Text::from_section(
+                            format!("+{}", total),
+                            TextStyle {
+                                font: font_spec.family.clone(),
+                                font_size: 20.0,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                bottom: Val::Px(0.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(ScorePopup::new(0.0, 40.0));
+            });
+    }
+}
+
+fn animate_ui_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popups: Query<(Entity, &mut ScorePopup, &mut Style, &mut Text)>,
+) {
+    for (entity, mut popup, mut style, mut text) in popups.iter_mut() {
+        popup.timer.tick(time.delta());
+        if popup.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = popup.timer.percent();
+        style.position.bottom = Val::Px(popup.start + popup.rise * progress);
+        text.sections[0].style.color.set_a(1.0 - progress);
+    }
+}
+
+fn animate_tile_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popups: Query<
+        (Entity, &mut ScorePopup, &mut Transform, &mut Text),
+        Without<Style>,
+    >,
+) {
+    for (entity, mut popup, mut transform, mut text) in popups.iter_mut() {
+        popup.timer.tick(time.delta());
+        if popup.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = popup.timer.percent();
+        transform.translation.y = popup.start + popup.rise * progress;
+        text.sections[0].style.color.set_a(1.0 - progress);
+    }
+}
+
+fn hint_text(
+    hint: Res<Hint>,
+    mut query_hint: Query<&mut Text, With<HintDisplay>>,
+) {
+    if !hint.is_changed() {
+        return;
+    }
+    let mut text = query_hint.single_mut();
+    text.sections[0].value = match hint.0 {
+        Some(board_shift) => format!("Hint: {:?}", board_shift),
+        None => String::new(),
+    };
+}
+
+fn mode_text(
+    game: Res<Game>,
+    mut query_mode: Query<&mut Text, With<ModeDisplay>>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+    let mut text = query_mode.single_mut();
+    text.sections[0].value = match game.puzzle {
+        Some(_) if game.solved => format!("{}: solved!", game.mode.label()),
+        Some(_) if game.moves_left() == Some(0) => {
+            format!("{}: out of moves", game.mode.label())
+        }
+        Some(puzzle) => format!("{}: {}", game.mode.label(), puzzle.goal.describe()),
+        None => match game.spec.as_ref().and_then(challenge_date) {
+            Some(date) => format!("{}: {}", game.mode.label(), date),
+            None => game.mode.label().to_string(),
+        },
+    };
+}
+
+fn moves_left_text(
+    game: Res<Game>,
+    mut query_box: Query<&mut Style, With<MovesBox>>,
+    mut query_moves: Query<&mut Text, With<MovesDisplay>>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+    let mut style = query_box.single_mut();
+    let Some(moves_left) = game.moves_left() else {
+        style.display = Display::None;
+        return;
+    };
+    style.display = Display::Flex;
+    query_moves.single_mut().sections[0].value = moves_left.to_string();
+}
+
+fn next_tile_text(
+    spawner: Res<TileSpawner>,
+    mut query_box: Query<&mut Style, With<NextTileBox>>,
+    mut query_next: Query<&mut Text, With<NextTileDisplay>>,
+) {
+    if !spawner.is_changed() {
+        return;
+    }
+    let mut style = query_box.single_mut();
+    let Some(upcoming) = spawner.upcoming else {
+        style.display = Display::None;
+        return;
+    };
+    style.display = Display::Flex;
+    query_next.single_mut().sections[0].value = upcoming.to_string();
+}
+
+fn countdown(
+    game: Res<Game>,
+    time: Res<Time>,
+    mut query_box: Query<(&mut Style, &mut BackgroundColor), With<TimeBox>>,
+    mut query_time: Query<&mut Text, With<TimeDisplay>>,
+) {
+    let (mut style, mut background) = query_box.single_mut();
+    let Some(time_left) = game.time_left() else {
+        style.display = Display::None;
+        return;
+    };
+    style.display = Display::Flex;
+
+    let seconds = time_left.ceil() as u32;
+    let mut text = query_time.single_mut();
+    text.sections[0].value = format!("{}:{:02}", seconds / 60, seconds % 60);
+
+    // Flash once a second once time is nearly up
+    let warning = time_left > 0.0
+        && time_left <= TIME_WARNING
+        && time.elapsed_seconds().fract() < 0.5;
+    *background = BackgroundColor(if warning {
+        Color::rgb(0.9, 0.3, 0.3)
+    } else {
+        MATERIALS.tile_placeholder
+    });
+}
+
 fn button_interaction_system(
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>),
+        (&Interaction, &mut BackgroundColor, &ButtonAction),
+        Changed<Interaction>,
     >,
-    mut run_state: ResMut<State<RunState>>,
+    mut button_writer: EventWriter<ButtonActionEvent>,
 ) {
-    for (interaction, mut color) 
+    for (interaction, mut color, action)
         in interaction_query.iter_mut() {
             match interaction {
                 Interaction::Clicked => {
                     *color = BUTTON_MATERIALS.pressed.into();
-
-                    match run_state.current() {
-                        RunState::Playing => {
-                            run_state
-                                .set(RunState::GameOver)
-                                .unwrap();
-                        }
-                        RunState::GameOver => {
-                            run_state
-                                .set(RunState::Playing)
-                                .unwrap();
-                        }
-                    }
+                    button_writer.send(ButtonActionEvent(*action));
                 }
                 Interaction::Hovered => {
                     *color = BUTTON_MATERIALS.hovered.into();
@@ -258,26 +1007,1541 @@ fn button_interaction_system(
         }
 }
 
-fn button_text_system(
-    button_query: Query<&Children, With<Button>>,
-    mut text_query: Query<&mut Text>,
-    run_state: Res<State<RunState>>,
+#[allow(clippy::too_many_arguments)]
+fn handle_button_actions(
+    mut button_reader: EventReader<ButtonActionEvent>,
+    mut run_state: ResMut<State<RunState>>,
+    mut bindings: ResMut<KeyBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut confirmation: ResMut<Confirmation>,
+    mut settings: ResMut<Settings>,
+    mut name_entry: ResMut<NameEntry>,
+    mut leaderboards: ResMut<Leaderboards>,
+    mut leaderboard_view: ResMut<LeaderboardView>,
+    mut pending_game: ResMut<PendingGame>,
+    mut custom_game: ResMut<CustomGame>,
+    mut calendar_view: ResMut<CalendarView>,
+    mut exit_writer: EventWriter<AppExit>,
 ) {
-    let children = button_query
-        .single();
-
-    let mut text = text_query
-        .get_mut(*children.first().expect(
-            "expect button to have a first child"
-        ))
-        .unwrap();
+    for ButtonActionEvent(action) in button_reader.iter() {
+        match action {
+            ButtonAction::ToggleGame => match run_state.current() {
+                RunState::Playing => {
+                    confirmation.ask(
+                        &mut run_state,
+                        "Abandon this game?",
+                        ButtonAction::EndGame,
+                    );
+                }
+                RunState::GameOver => {
+                    run_state
+                        .set(RunState::Playing)
+                        .unwrap();
+                }
+                RunState::MainMenu
+                | RunState::Paused
+                | RunState::Settings
+                | RunState::Bindings
+                | RunState::Confirm
+                | RunState::Stats
+                | RunState::Leaderboard
+                | RunState::LevelSelect
+                | RunState::Daily
+                | RunState::Share
+                | RunState::EnterCode
+                | RunState::Editor => {}
+            },
+            ButtonAction::PlayCode if pending_game.0.is_none() => {}
+            ButtonAction::NewGame
+            | ButtonAction::PlayLevel(_)
+            | ButtonAction::PlayDaily
+            | ButtonAction::PlayCode
+            | ButtonAction::PlayLayout => {
+                if *action == ButtonAction::NewGame {
+                    pending_game.0 = None;
+                }
+                if run_state.inactives().contains(&RunState::Playing) {
+                    confirmation.ask(
+                        &mut run_state,
+                        "Abandon the game in progress?",
+                        ButtonAction::StartGame,
+                    );
+                } else {
+                    custom_game.0 = pending_game.0.take();
+                    run_state
+                        .replace(RunState::Playing)
+                        .unwrap();
+                }
+            }
+            ButtonAction::StartGame => {
+                custom_game.0 = pending_game.0.take();
+                // Unwinds any game left on the stack, so it starts afresh
+                run_state
+                    .replace(RunState::Playing)
+                    .unwrap();
+            }
+            ButtonAction::OpenLevels => {
+                run_state
+                    .push(RunState::LevelSelect)
+                    .unwrap();
+            }
+            ButtonAction::OpenDaily => {
+                let today = Date::today();
+                calendar_view.year = today.year;
+                calendar_view.month = today.month;
+                run_state
+                    .push(RunState::Daily)
+                    .unwrap();
+            }
+            ButtonAction::OpenShare => {
+                run_state
+                    .push(RunState::Share)
+                    .unwrap();
+            }
+            ButtonAction::OpenEditor => {
+                if run_state.inactives().contains(&RunState::Playing) {
+                    confirmation.ask(
+                        &mut run_state,
+                        "Abandon the game in progress?",
+                        ButtonAction::StartEditor,
+                    );
+                } else {
+                    run_state
+                        .replace(RunState::Editor)
+                        .unwrap();
+                }
+            }
+            ButtonAction::StartEditor => {
+                run_state
+                    .replace(RunState::Editor)
+                    .unwrap();
+            }
+            // The editor's own system lays out the tiles
+            ButtonAction::SandboxSize(_) | ButtonAction::ClearBoard => {}
+            // Positions are saved and loaded by the notation plugin
+            ButtonAction::SavePosition
+            | ButtonAction::LoadPosition
+            | ButtonAction::CopyPosition
+            | ButtonAction::PastePosition => {}
+            // Codes are copied and pasted by the challenge plugin
+            ButtonAction::CopyCode | ButtonAction::PasteCode => {}
+            ButtonAction::OpenCodeEntry => {
+                run_state
+                    .push(RunState::EnterCode)
+                    .unwrap();
+            }
+            ButtonAction::DailyMonth(step) => {
+                let month = calendar_view.year * 12 + i32::from(calendar_view.month) - 1 + step;
+                calendar_view.year = month.div_euclid(12);
+                calendar_view.month = month.rem_euclid(12) as u8 + 1;
+            }
+            ButtonAction::EndGame => {
+                if *run_state.current() == RunState::Playing {
+                    run_state
+                        .set(RunState::GameOver)
+                        .unwrap();
+                }
+            }
+            ButtonAction::RestartGame => {
+                if *run_state.current() == RunState::Playing {
+                    run_state.restart().unwrap();
+                }
+            }
+            ButtonAction::Continue => {
+                run_state.pop().unwrap();
+            }
+            ButtonAction::Pause => {
+                if *run_state.current() == RunState::Playing {
+                    let _ = run_state.push(RunState::Paused);
+                }
+            }
+            ButtonAction::MainMenu => {
+                run_state
+                    .set(RunState::MainMenu)
+                    .unwrap();
+            }
+            ButtonAction::Quit => {
+                exit_writer.send(AppExit);
+            }
+            ButtonAction::OpenSettings => {
+                run_state
+                    .push(RunState::Settings)
+                    .unwrap();
+            }
+            ButtonAction::AdjustSetting(kind, step) => {
+                settings.adjust(*kind, *step);
+                settings.save();
+            }
+            ButtonAction::OpenBindings => {
+                if *run_state.current() != RunState::Bindings {
+                    run_state
+                        .push(RunState::Bindings)
+                        .unwrap();
+                }
+            }
+            ButtonAction::OpenStats => {
+                run_state
+                    .push(RunState::Stats)
+                    .unwrap();
+            }
+            ButtonAction::OpenLeaderboard => {
+                leaderboard_view.board_size = settings.board_size;
+                leaderboard_view.mode = settings.mode;
+                run_state
+                    .push(RunState::Leaderboard)
+                    .unwrap();
+            }
+            ButtonAction::LeaderboardPage(step) => {
+                let pages: Vec<(GameMode, u8)> = GameMode::ALL
+                    .iter()
+                    .flat_map(|mode| BOARD_SIZES.iter().map(move |size| (*mode, *size)))
+                    .collect();
+                let current = (leaderboard_view.mode, leaderboard_view.board_size);
+                (leaderboard_view.mode, leaderboard_view.board_size) =
+                    step_through(&pages, current, *step);
+            }
+            ButtonAction::SubmitScore => {
+                name_entry.submit(&mut leaderboards);
+            }
+            ButtonAction::SkipScore => {
+                name_entry.skip();
+            }
+            ButtonAction::CloseScreen => {
+                run_state.pop().unwrap();
+            }
+            ButtonAction::Confirm => {
+                confirmation.confirmed = true;
+                run_state.pop().unwrap();
+            }
+            ButtonAction::AddBinding(action) => {
+                rebinding.waiting = Some(*action);
+                rebinding.message = format!(
+                    "Press a key for {}, or Escape to cancel",
+                    action.label(),
+                );
+            }
+            ButtonAction::ClearBinding(action) => {
+                bindings.clear(*action);
+                bindings.save();
+                rebinding.waiting = None;
+                rebinding.message = format!(
+                    "Cleared the keys for {}",
+                    action.label(),
+                );
+            }
+            ButtonAction::ResetBindings => {
+                *bindings = KeyBindings::default();
+                bindings.save();
+                rebinding.waiting = None;
+                rebinding.message = "Restored the default keys".to_string();
+            }
+        }
+    }
+}
 
-    match run_state.current() {
-        RunState::Playing => {
-            text.sections[0].value = "End Game".to_string();
+/// Lets the keyboard move between buttons whenever the board isn't taking
+/// the arrow keys for itself.
+fn keyboard_menu_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    run_state: Res<State<RunState>>,
+    rebinding: Res<Rebinding>,
+    name_entry: Res<NameEntry>,
+    mut menu_writer: EventWriter<MenuInputEvent>,
+) {
+    if rebinding.waiting.is_some() {
+        return;
+    }
+    // Letters, space and the arrows are for editing the name
+    if name_entry.is_pending() {
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            menu_writer.send(MenuInputEvent(MenuInput::Activate));
         }
-        RunState::GameOver => {
-            text.sections[0].value = "New Game".to_string();
+        if keyboard_input.just_pressed(KeyCode::Tab) {
+            menu_writer.send(MenuInputEvent(MenuInput::Next));
+        }
+        return;
+    }
+    if *run_state.current() == RunState::Playing {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            menu_writer.send(MenuInputEvent(MenuInput::Menu));
         }
+        return;
+    }
+    let shift = keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    for key in keyboard_input.get_just_pressed() {
+        let input = match key {
+            KeyCode::Up | KeyCode::Left => MenuInput::Previous,
+            KeyCode::Tab if shift => MenuInput::Previous,
+            KeyCode::Down | KeyCode::Right | KeyCode::Tab => MenuInput::Next,
+            KeyCode::Return | KeyCode::Space => MenuInput::Activate,
+            KeyCode::Escape => MenuInput::Back,
+            _ => continue,
+        };
+        menu_writer.send(MenuInputEvent(input));
+    }
+}
+
+fn menu_focus(
+    mut menu_reader: EventReader<MenuInputEvent>,
+    mut focus: ResMut<ButtonFocus>,
+    buttons: Query<(Entity, &GlobalTransform, &ButtonAction)>,
+    parents: Query<&Parent>,
+    screens: Query<&Screen>,
+    run_state: Res<State<RunState>>,
+    mut button_writer: EventWriter<ButtonActionEvent>,
+) {
+    for MenuInputEvent(input) in menu_reader.iter() {
+        // Only the buttons on the topmost screen, or the HUD when there's none
+        let layer_of = |entity: Entity| {
+            std::iter::successors(Some(entity), |e| parents.get(*e).ok().map(|p| p.get()))
+                .find_map(|e| screens.get(e).ok())
+                .map(|screen| screen.layer)
+        };
+        let top_layer = screens.iter().map(|screen| screen.layer).max();
+        let mut reachable: Vec<(Entity, Vec3, ButtonAction)> = buttons
+            .iter()
+            .filter(|(entity, _, _)| layer_of(*entity) == top_layer)
+            .map(|(entity, transform, action)| (entity, transform.translation(), *action))
+            .collect();
+        // Reading order: top to bottom, then left to right. UI y points up
+        reachable.sort_by(|a, b| {
+            b.1.y.total_cmp(&a.1.y).then(a.1.x.total_cmp(&b.1.x))
+        });
+
+        let current = focus
+            .0
+            .and_then(|focused| reachable.iter().position(|(entity, _, _)| *entity == focused));
+
+        match input {
+            MenuInput::Previous | MenuInput::Next if !reachable.is_empty() => {
+                let len = reachable.len();
+                let index = match (current, input) {
+                    (None, _) => 0,
+                    (Some(index), MenuInput::Previous) => (index + len - 1) % len,
+                    (Some(index), _) => (index + 1) % len,
+                };
+                focus.0 = Some(reachable[index].0);
+            }
+            MenuInput::Previous | MenuInput::Next => {}
+            MenuInput::Activate => {
+                if let Some(index) = current {
+                    button_writer.send(ButtonActionEvent(reachable[index].2));
+                }
+            }
+            MenuInput::Back => {
+                if !run_state.inactives().is_empty() {
+                    button_writer.send(ButtonActionEvent(ButtonAction::CloseScreen));
+                }
+            }
+            MenuInput::Menu => match run_state.current() {
+                RunState::Playing => {
+                    button_writer.send(ButtonActionEvent(ButtonAction::Pause));
+                }
+                RunState::Paused => {
+                    button_writer.send(ButtonActionEvent(ButtonAction::CloseScreen));
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+fn focus_highlight(
+    focus: Res<ButtonFocus>,
+    mut buttons: Query<(Entity, &Interaction, &mut BackgroundColor), With<ButtonAction>>,
+) {
+    if !focus.is_changed() {
+        return;
+    }
+    for (entity, interaction, mut color) in buttons.iter_mut() {
+        if *interaction == Interaction::None {
+            *color = if focus.0 == Some(entity) {
+                BUTTON_MATERIALS.hovered.into()
+            } else {
+                BUTTON_MATERIALS.normal.into()
+            };
+        }
+    }
+}
+
+fn button_text_system(
+    button_query: Query<(&Children, &ButtonAction)>,
+    mut text_query: Query<&mut Text>,
+    run_state: Res<State<RunState>>,
+) {
+    for (children, action) in button_query.iter() {
+        if *action != ButtonAction::ToggleGame {
+            continue;
+        }
+
+        let mut text = text_query
+            .get_mut(*children.first().expect(
+                "expect button to have a first child"
+            ))
+            .unwrap();
+
+        match run_state.current() {
+            RunState::Playing => {
+                text.sections[0].value = "End Game".to_string();
+            }
+            RunState::GameOver => {
+                text.sections[0].value = "New Game".to_string();
+            }
+            RunState::MainMenu
+            | RunState::Paused
+            | RunState::Settings
+            | RunState::Bindings
+            | RunState::Confirm
+            | RunState::Stats
+            | RunState::Leaderboard
+            | RunState::LevelSelect
+            | RunState::Daily
+            | RunState::Share
+            | RunState::EnterCode
+            | RunState::Editor => {}
+        }
+    }
+}
+
+/// Background of screens that replace the game entirely.
+const SCREEN_OPAQUE: Color = Color::rgb(0.1, 0.1, 0.15);
+/// Background of screens that leave the game visible underneath.
+const SCREEN_DIM: Color = Color::rgba(0.1, 0.1, 0.15, 0.6);
+
+/// Full-window node screens are built in, drawn over the board, the HUD
+/// and any screen on a lower `layer`, and blocking clicks from reaching them.
+fn screen_root(layer: i32, background: Color) -> (NodeBundle, Screen) {
+    (
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: BackgroundColor(background),
+            z_index: ZIndex::Global(10 * layer),
+            ..Default::default()
+        },
+        Screen { layer },
+    )
+}
+
+fn spawn_text<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    font_spec: &FontSpec,
+    value: &str,
+    font_size: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    parent
+        .spawn(TextBundle {
+            text: Text::from_section(
+                value,
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size,
+                    color: Color::WHITE,
+                },
+            ),
+            style: Style {
+                margin: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+}
+
+fn despawn_screen<T: Component>(
+    mut commands: Commands,
+    screens: Query<Entity, With<T>>,
+) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn setup_main_menu(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    run_state: Res<State<RunState>>,
+) {
+    let in_progress = run_state.inactives().contains(&RunState::Playing);
+
+    commands
+        .spawn(screen_root(1, SCREEN_OPAQUE))
+        .insert(MainMenuScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "2048", 80.0);
+            if in_progress {
+                spawn_button(parent, &font_spec, "Continue", ButtonAction::Continue);
+            }
+            spawn_button(parent, &font_spec, "New Game", ButtonAction::NewGame);
+            spawn_button(parent, &font_spec, "Puzzles", ButtonAction::OpenLevels);
+            spawn_button(parent, &font_spec, "Daily Challenge", ButtonAction::OpenDaily);
+            spawn_button(parent, &font_spec, "Enter Code", ButtonAction::OpenCodeEntry);
+            spawn_button(parent, &font_spec, "Sandbox", ButtonAction::OpenEditor);
+            spawn_button(parent, &font_spec, "High Scores", ButtonAction::OpenLeaderboard);
+            spawn_button(parent, &font_spec, "Stats", ButtonAction::OpenStats);
+            spawn_button(parent, &font_spec, "Settings", ButtonAction::OpenSettings);
+            spawn_button(parent, &font_spec, "Quit", ButtonAction::Quit);
+        });
+}
+
+fn setup_pause_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+) {
+    commands
+        .spawn(screen_root(1, SCREEN_DIM))
+        .insert(PauseScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Paused", 40.0);
+            spawn_button(parent, &font_spec, "Resume", ButtonAction::CloseScreen);
+            spawn_button(parent, &font_spec, "Share Game", ButtonAction::OpenShare);
+            spawn_button(parent, &font_spec, "Settings", ButtonAction::OpenSettings);
+            spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+            spawn_button(parent, &font_spec, "Quit", ButtonAction::Quit);
+        });
+}
+
+fn setup_confirm_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    confirmation: Res<Confirmation>,
+    mut focus: ResMut<ButtonFocus>,
+) {
+    commands
+        .spawn(screen_root(4, SCREEN_DIM))
+        .insert(ConfirmScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.board),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_text(parent, &font_spec, &confirmation.message, 30.0);
+                    parent
+                        .spawn(NodeBundle {
+                            background_color: BackgroundColor(MATERIALS.none),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_button(parent, &font_spec, "Yes", ButtonAction::Confirm);
+                            // Keyboard and gamepad players start on the safe choice
+                            focus.0 = Some(spawn_button(
+                                parent,
+                                &font_spec,
+                                "No",
+                                ButtonAction::CloseScreen,
+                            ));
+                        });
+                });
+        });
+}
+
+fn send_confirmed_action(
+    mut confirmation: ResMut<Confirmation>,
+    mut button_writer: EventWriter<ButtonActionEvent>,
+) {
+    let on_confirm = confirmation.on_confirm.take();
+    if confirmation.confirmed {
+        if let Some(action) = on_confirm {
+            button_writer.send(ButtonActionEvent(action));
+        }
+    }
+    confirmation.confirmed = false;
+}
+
+fn setup_settings_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+) {
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(SettingsScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Settings", 40.0);
+
+            for kind in SettingKind::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(MATERIALS.none),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(200.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(parent, &font_spec, kind.label(), 20.0);
+                            });
+                        spawn_button(parent, &font_spec, "<", ButtonAction::AdjustSetting(kind, -1));
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(140.0), Val::Auto),
+                                    justify_content: JustifyContent::Center,
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(parent, &font_spec, "", 20.0)
+                                    .insert(SettingValueText(kind));
+                            });
+                        spawn_button(parent, &font_spec, ">", ButtonAction::AdjustSetting(kind, 1));
+                    });
+            }
+
+            spawn_text(parent, &font_spec, "A new mode or board size applies from the next game", 15.0);
+
+            parent
+                .spawn(NodeBundle {
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font_spec, "Key Bindings", ButtonAction::OpenBindings);
+                    spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+                });
+        });
+}
+
+fn settings_text(
+    settings: Res<Settings>,
+    mut texts: Query<(&mut Text, &SettingValueText)>,
+) {
+    for (mut text, SettingValueText(kind)) in texts.iter_mut() {
+        text.sections[0].value = settings.display(*kind);
+    }
+}
+
+fn setup_bindings_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    *rebinding = Rebinding::default();
+
+    commands
+        .spawn(screen_root(3, SCREEN_OPAQUE))
+        .insert(BindingsScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Key Bindings", 40.0);
+
+            for action in Action::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(MATERIALS.none),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(100.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(parent, &font_spec, action.label(), 20.0);
+                            });
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(300.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(parent, &font_spec, "", 20.0)
+                                    .insert(BindingKeysText(action));
+                            });
+                        spawn_button(parent, &font_spec, "Add", ButtonAction::AddBinding(action));
+                        spawn_button(parent, &font_spec, "Clear", ButtonAction::ClearBinding(action));
+                    });
+            }
+
+            spawn_text(parent, &font_spec, "", 20.0)
+                .insert(BindingStatusText);
+
+            parent
+                .spawn(NodeBundle {
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font_spec, "Defaults", ButtonAction::ResetBindings);
+                    spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+                });
+        });
+}
+
+fn rebind_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut bindings: ResMut<KeyBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some(action) = rebinding.waiting else {
+        return;
+    };
+    let Some(key) = keyboard_input.get_just_pressed().next().copied() else {
+        return;
+    };
+
+    rebinding.waiting = None;
+    if key == KeyCode::Escape {
+        rebinding.message = String::new();
+        return;
+    }
+    rebinding.message = match bindings.bind(key, action) {
+        Ok(()) => {
+            bindings.save();
+            format!("Bound {} to {}", key_name(key), action.label())
+        }
+        Err(error) => error.to_string(),
+    };
+}
+
+#[allow(clippy::type_complexity)]
+fn bindings_text(
+    bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    mut query_texts: ParamSet<(
+        Query<(&mut Text, &BindingKeysText)>,
+        Query<&mut Text, With<BindingStatusText>>,
+    )>,
+) {
+    for (mut text, BindingKeysText(action)) in query_texts.p0().iter_mut() {
+        let keys = bindings
+            .keys(*action)
+            .map(key_name)
+            .collect::<Vec<_>>();
+        text.sections[0].value = if keys.is_empty() {
+            "-".to_string()
+        } else {
+            keys.join(", ")
+        };
+    }
+    for mut text in query_texts.p1().iter_mut() {
+        text.sections[0].value = rebinding.message.clone();
+    }
+}
+
+/// Longest bar in a stats chart, the others scaled to match.
+const CHART_BAR_WIDTH: f32 = 200.0;
+
+/// A horizontal bar chart, one labelled row per entry, with bars sized
+/// relative to the largest count.
+fn spawn_bar_chart(
+    parent: &mut ChildBuilder,
+    font_spec: &FontSpec,
+    title: &str,
+    rows: &[(String, u32)],
+) {
+    let most = rows.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                margin: UiRect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            background_color: BackgroundColor(MATERIALS.none),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            spawn_text(parent, font_spec, title, 20.0);
+            if rows.is_empty() {
+                spawn_text(parent, font_spec, "No games yet", 15.0);
+            }
+            for (label, count) in rows {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(MATERIALS.none),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(80.0), Val::Auto),
+                                    justify_content: JustifyContent::FlexEnd,
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(parent, font_spec, label, 15.0);
+                            });
+                        parent.spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Px(CHART_BAR_WIDTH * *count as f32 / most as f32),
+                                    Val::Px(14.0),
+                                ),
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(BUTTON_MATERIALS.normal),
+                            ..Default::default()
+                        });
+                        spawn_text(parent, font_spec, &count.to_string(), 15.0);
+                    });
+            }
+        });
+}
+
+fn setup_stats_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    stats: Res<Statistics>,
+) {
+    let best_scores = if stats.best_scores.is_empty() {
+        "-".to_string()
+    } else {
+        stats
+            .best_scores
+            .iter()
+            .map(|(size, score)| format!("{0}x{0}: {1}", size, score))
+            .collect::<Vec<_>>()
+            .join("   ")
+    };
+    let lines = [
+        format!("Games played: {}", stats.games_played),
+        format!("Wins: {} ({:.0}%)", stats.wins, stats.win_rate() * 100.0),
+        format!("Average score: {}", stats.average_score()),
+        format!("Highest tile: {}", stats.highest_tile),
+        format!("Total merges: {}", stats.total_merges),
+        format!(
+            "Longest game: {} moves, {}:{:02}",
+            stats.longest_game_moves,
+            stats.longest_game_duration as u32 / 60,
+            stats.longest_game_duration as u32 % 60,
+        ),
+        format!("Best scores: {}", best_scores),
+    ];
+    let scores = stats
+        .score_distribution
+        .iter()
+        .map(|(bucket, count)| (format!("{}+", bucket), *count))
+        .collect::<Vec<_>>();
+    let max_tiles = stats
+        .max_tile_distribution
+        .iter()
+        .map(|(tile, count)| (tile.to_string(), *count))
+        .collect::<Vec<_>>();
+
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(StatsScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Statistics", 40.0);
+            for line in &lines {
+                spawn_text(parent, &font_spec, line, 20.0);
+            }
+            parent
+                .spawn(NodeBundle {
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_bar_chart(parent, &font_spec, "Scores", &scores);
+                    spawn_bar_chart(parent, &font_spec, "Biggest tile", &max_tiles);
+                });
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+fn name_entry_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    name_entry: Res<NameEntry>,
+    screens: Query<Entity, With<NameEntryScreen>>,
+    mut focus: ResMut<ButtonFocus>,
+) {
+    if !name_entry.is_changed() {
+        return;
+    }
+    if !name_entry.is_pending() {
+        for entity in screens.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if !screens.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn(screen_root(1, SCREEN_DIM))
+        .insert(NameEntryScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Game Over", 40.0);
+            spawn_text(
+                parent,
+                &font_spec,
+                &format!("New high score: {}", name_entry.score()),
+                25.0,
+            );
+            spawn_text(parent, &font_spec, "Enter your name", 20.0);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(260.0), Val::Px(40.0)),
+                        align_items: AlignItems::Center,
+                        padding: UiRect::horizontal(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.board),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_text(parent, &font_spec, "", 25.0)
+                        .insert(NameEntryText);
+                });
+            parent
+                .spawn(NodeBundle {
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // So Enter saves straight away
+                    focus.0 = Some(spawn_button(
+                        parent,
+                        &font_spec,
+                        "Save",
+                        ButtonAction::SubmitScore,
+                    ));
+                    spawn_button(parent, &font_spec, "Skip", ButtonAction::SkipScore);
+                });
+        });
+}
+
+fn name_entry_text(
+    name_entry: Res<NameEntry>,
+    mut texts: Query<&mut Text, With<NameEntryText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!("{}_", name_entry.name);
+    }
+}
+
+/// Column headings and widths of the leaderboard table.
+const LEADERBOARD_COLUMNS: [(&str, f32); 8] = [
+    ("#", 40.0),
+    ("Name", 200.0),
+    ("Score", 90.0),
+    ("Tile", 70.0),
+    ("Moves", 80.0),
+    ("Time", 70.0),
+    ("Eligible", 90.0),
+    ("Date", 120.0),
+];
+
+fn setup_leaderboard_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+) {
+    let spawn_row = |parent: &mut ChildBuilder, row: Option<usize>| {
+        parent
+            .spawn(NodeBundle {
+                background_color: BackgroundColor(MATERIALS.none),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for (column, (heading, width)) in LEADERBOARD_COLUMNS.iter().enumerate() {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(*width), Val::Auto),
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(MATERIALS.none),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| match row {
+                            Some(row) => {
+                                spawn_text(parent, &font_spec, "", 18.0)
+                                    .insert(LeaderboardCell { row, column });
+                            }
+                            None => {
+                                spawn_text(parent, &font_spec, heading, 18.0);
+                            }
+                        });
+                }
+            });
+    };
+
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(LeaderboardScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "High Scores", 40.0);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font_spec, "<", ButtonAction::LeaderboardPage(-1));
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(260.0), Val::Auto),
+                                justify_content: JustifyContent::Center,
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(MATERIALS.none),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_text(parent, &font_spec, "", 20.0)
+                                .insert(LeaderboardTitleText);
+                        });
+                    spawn_button(parent, &font_spec, ">", ButtonAction::LeaderboardPage(1));
+                });
+            spawn_row(parent, None);
+            for row in 0..LEADERBOARD_SIZE {
+                spawn_row(parent, Some(row));
+            }
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn leaderboard_text(
+    leaderboards: Res<Leaderboards>,
+    view: Res<LeaderboardView>,
+    mut query_texts: ParamSet<(
+        Query<&mut Text, With<LeaderboardTitleText>>,
+        Query<(&mut Text, &LeaderboardCell)>,
+    )>,
+) {
+    for mut text in query_texts.p0().iter_mut() {
+        text.sections[0].value = format!("{0}x{0} {1}", view.board_size, view.mode.label());
+        if view.mode.leaderboard_eligible() {
+            text.sections[0].value.push_str(" (eligible)");
+        }
+    }
+    let entries = leaderboards.entries(view.board_size, view.mode);
+    for (mut text, cell) in query_texts.p1().iter_mut() {
+        text.sections[0].value = match entries.get(cell.row) {
+            None => String::new(),
+            Some(entry) => match cell.column {
+                0 => format!("{}", cell.row + 1),
+                1 => entry.name.clone(),
+                2 => entry.score.to_string(),
+                3 => entry.max_tile.to_string(),
+                4 => entry.moves.to_string(),
+                5 => format!(
+                    "{}:{:02}",
+                    entry.duration as u32 / 60,
+                    entry.duration as u32 % 60,
+                ),
+                6 => if entry.eligible { "Yes" } else { "" }.to_string(),
+                _ => entry.date.to_string(),
+            },
+        };
+    }
+}
+
+/// Puts the level, daily challenge, code or layout picked up as the next game.
+#[allow(clippy::too_many_arguments)]
+fn queue_game(
+    mut button_reader: EventReader<ButtonActionEvent>,
+    levels: Res<Levels>,
+    packs: Res<Assets<LevelPack>>,
+    mut code_entry: ResMut<CodeEntry>,
+    tiles: Query<(&Position, &Points)>,
+    blockers: Query<&Position, With<Blocker>>,
+    sandbox: Res<Sandbox>,
+    settings: Res<Settings>,
+    mut pending_game: ResMut<PendingGame>,
+) {
+    for ButtonActionEvent(action) in button_reader.iter() {
+        match action {
+            ButtonAction::PlayLevel(index) => {
+                pending_game.0 = levels
+                    .get(&packs)
+                    .and_then(|levels| levels.get(*index))
+                    .map(|level| level.spec());
+            }
+            ButtonAction::PlayDaily => {
+                pending_game.0 = Some(daily_spec(Date::today()));
+            }
+            ButtonAction::PlayCode => match decode(&code_entry.code) {
+                Ok(spec) => {
+                    pending_game.0 = Some(spec);
+                }
+                Err(error) => {
+                    pending_game.0 = None;
+                    code_entry.error = Some(error);
+                }
+            },
+            ButtonAction::PlayLayout => {
+                let layout = tiles
+                    .iter()
+                    .map(|(position, points)| (*position, points.value))
+                    .collect();
+                let blockers = blockers.iter().copied().collect();
+                pending_game.0 = Some(sandbox.spec(layout, blockers, settings.four_chance));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn setup_level_select_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    levels: Res<Levels>,
+    packs: Res<Assets<LevelPack>>,
+    progress: Res<LevelProgress>,
+) {
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(LevelSelectScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Puzzles", 40.0);
+
+            let Some(levels) = levels.get(&packs) else {
+                spawn_text(parent, &font_spec, "Loading levels...", 20.0);
+                spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+                return;
+            };
+            for (index, level) in levels.iter().enumerate() {
+                let stars = progress.stars(level);
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(MATERIALS.none),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(260.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(
+                                    parent,
+                                    &font_spec,
+                                    &format!("{}. {}", index + 1, level.name),
+                                    20.0,
+                                );
+                            });
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(200.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_text(
+                                    parent,
+                                    &font_spec,
+                                    &format!(
+                                        "{} in {} moves",
+                                        level.goal.describe(),
+                                        level.moves,
+                                    ),
+                                    15.0,
+                                );
+                            });
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(120.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                background_color: BackgroundColor(MATERIALS.none),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                let rating = if stars == 0 {
+                                    "Unsolved".to_string()
+                                } else {
+                                    format!("{}/3 stars", stars)
+                                };
+                                spawn_text(parent, &font_spec, &rating, 15.0);
+                            });
+                        spawn_button(parent, &font_spec, "Play", ButtonAction::PlayLevel(index));
+                    });
+            }
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Enough weeks to fit any month, whichever day it starts on.
+const CALENDAR_WEEKS: usize = 6;
+
+fn setup_daily_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    history: Res<DailyHistory>,
+) {
+    let spawn_cell = |parent: &mut ChildBuilder, cell: Option<usize>, heading: &str| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(70.0), Val::Px(44.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(MATERIALS.none),
+                ..Default::default()
+            })
+            .with_children(|parent| match cell {
+                Some(cell) => {
+                    spawn_text(parent, &font_spec, "", 14.0)
+                        .insert(CalendarCell(cell));
+                }
+                None => {
+                    spawn_text(parent, &font_spec, heading, 16.0);
+                }
+            });
+    };
+    let spawn_row = |parent: &mut ChildBuilder, week: Option<usize>| {
+        parent
+            .spawn(NodeBundle {
+                background_color: BackgroundColor(MATERIALS.none),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for (weekday, heading) in WEEKDAY_NAMES.iter().enumerate() {
+                    spawn_cell(parent, week.map(|week| week * 7 + weekday), heading);
+                }
+            });
+    };
+
+    let today = Date::today();
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(DailyScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Daily Challenge", 40.0);
+            match history.result(today) {
+                Some(result) => {
+                    spawn_text(
+                        parent,
+                        &font_spec,
+                        &format!("Today's score: {}. Replays don't count.", result.score),
+                        18.0,
+                    );
+                    spawn_button(parent, &font_spec, "Replay", ButtonAction::PlayDaily);
+                }
+                None => {
+                    spawn_text(
+                        parent,
+                        &font_spec,
+                        "Everyone gets the same board today. Only your first game counts.",
+                        18.0,
+                    );
+                    spawn_button(parent, &font_spec, "Play", ButtonAction::PlayDaily);
+                }
+            }
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font_spec, "<", ButtonAction::DailyMonth(-1));
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(260.0), Val::Auto),
+                                justify_content: JustifyContent::Center,
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(MATERIALS.none),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_text(parent, &font_spec, "", 20.0)
+                                .insert(CalendarTitleText);
+                        });
+                    spawn_button(parent, &font_spec, ">", ButtonAction::DailyMonth(1));
+                });
+            spawn_row(parent, None);
+            for week in 0..CALENDAR_WEEKS {
+                spawn_row(parent, Some(week));
+            }
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+/// Fills in the calendar with each day's score, for days that have one.
+#[allow(clippy::type_complexity)]
+fn calendar_text(
+    history: Res<DailyHistory>,
+    view: Res<CalendarView>,
+    mut query_texts: ParamSet<(
+        Query<&mut Text, With<CalendarTitleText>>,
+        Query<(&mut Text, &CalendarCell)>,
+    )>,
+) {
+    for mut text in query_texts.p0().iter_mut() {
+        let month = MONTH_NAMES[usize::from(view.month - 1)];
+        text.sections[0].value = format!("{} {}", month, view.year);
+    }
+    let first = Date {
+        year: view.year,
+        month: view.month,
+        day: 1,
+    };
+    let offset = usize::from(first.weekday());
+    let days = usize::from(first.days_in_month());
+    for (mut text, CalendarCell(cell)) in query_texts.p1().iter_mut() {
+        text.sections[0].value = if *cell < offset || *cell >= offset + days {
+            String::new()
+        } else {
+            let date = Date {
+                day: (cell - offset + 1) as u8,
+                ..first
+            };
+            match history.result(date) {
+                Some(result) => format!("{}\n{}", date.day, result.score),
+                None => date.day.to_string(),
+            }
+        };
+    }
+}
+
+fn setup_share_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    game: Res<Game>,
+    mut status: ResMut<PositionStatus>,
+    mut code_entry: ResMut<CodeEntry>,
+) {
+    status.0.clear();
+    code_entry.status.clear();
+    let code = game.spec.as_ref().map(encode).unwrap_or_default();
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(ShareScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Share Game", 40.0);
+            spawn_text(parent, &font_spec, "Anyone who enters this code plays the same game:", 18.0);
+            spawn_text(parent, &font_spec, &code, 25.0);
+            spawn_button(parent, &font_spec, "Copy Code", ButtonAction::CopyCode);
+            spawn_text(parent, &font_spec, "", 15.0)
+                .insert(CodeStatusText);
+            spawn_text(
+                parent,
+                &font_spec,
+                "Enter it from the main menu, or start the game with --challenge <code>",
+                15.0,
+            );
+            spawn_text(
+                parent,
+                &font_spec,
+                "Or save or copy the board as text, to carry on from with --board <file>",
+                15.0,
+            );
+            spawn_button(parent, &font_spec, "Save Position", ButtonAction::SavePosition);
+            spawn_button(parent, &font_spec, "Copy Position", ButtonAction::CopyPosition);
+            spawn_text(parent, &font_spec, "", 15.0)
+                .insert(PositionStatusText);
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+fn setup_code_entry_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    mut code_entry: ResMut<CodeEntry>,
+) {
+    code_entry.error = None;
+    code_entry.status.clear();
+    commands
+        .spawn(screen_root(2, SCREEN_OPAQUE))
+        .insert(CodeEntryScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Enter Code", 40.0);
+            spawn_text(
+                parent,
+                &font_spec,
+                "Type in or paste (Ctrl+V) a code shared from another game",
+                18.0,
+            );
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(520.0), Val::Px(40.0)),
+                        align_items: AlignItems::Center,
+                        padding: UiRect::horizontal(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.board),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_text(parent, &font_spec, "", 20.0)
+                        .insert(CodeEntryText);
+                });
+            spawn_text(parent, &font_spec, "", 15.0)
+                .insert(CodeErrorText);
+            spawn_text(parent, &font_spec, "", 15.0)
+                .insert(CodeStatusText);
+            spawn_button(parent, &font_spec, "Paste", ButtonAction::PasteCode);
+            spawn_button(parent, &font_spec, "Play", ButtonAction::PlayCode);
+            spawn_button(parent, &font_spec, "Back", ButtonAction::CloseScreen);
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn code_entry_text(
+    code_entry: Res<CodeEntry>,
+    mut query_texts: ParamSet<(
+        Query<&mut Text, With<CodeEntryText>>,
+        Query<&mut Text, With<CodeErrorText>>,
+    )>,
+) {
+    if !code_entry.is_changed() {
+        return;
+    }
+    for mut text in query_texts.p0().iter_mut() {
+        text.sections[0].value = format!("{}_", code_entry.code);
+    }
+    for mut text in query_texts.p1().iter_mut() {
+        text.sections[0].value = code_entry
+            .error
+            .map(|error| error.to_string())
+            .unwrap_or_default();
+    }
+}
+
+/// Editing controls down the side, leaving the board clear to click on.
+fn setup_editor_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    mut status: ResMut<PositionStatus>,
+) {
+    status.0.clear();
+    let (mut root, screen) = screen_root(1, MATERIALS.none);
+    root.style.align_items = AlignItems::FlexStart;
+    root.style.padding = UiRect::all(Val::Px(50.0));
+    commands
+        .spawn((root, screen))
+        .insert(EditorScreen)
+        .with_children(|parent| {
+            spawn_text(parent, &font_spec, "Sandbox", 40.0);
+            spawn_text(parent, &font_spec, "Left click: add a 2 or double", 15.0);
+            spawn_text(parent, &font_spec, "Right click: halve or remove", 15.0);
+            spawn_text(parent, &font_spec, "Middle click: add or remove a blocker", 15.0);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(MATERIALS.none),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font_spec, "<", ButtonAction::SandboxSize(-1));
+                    spawn_text(parent, &font_spec, "", 20.0)
+                        .insert(SandboxSizeText);
+                    spawn_button(parent, &font_spec, ">", ButtonAction::SandboxSize(1));
+                });
+            spawn_button(parent, &font_spec, "Play", ButtonAction::PlayLayout);
+            spawn_button(parent, &font_spec, "Clear", ButtonAction::ClearBoard);
+            spawn_button(parent, &font_spec, "Save Position", ButtonAction::SavePosition);
+            spawn_button(parent, &font_spec, "Load Position", ButtonAction::LoadPosition);
+            spawn_button(parent, &font_spec, "Copy Position", ButtonAction::CopyPosition);
+            spawn_button(parent, &font_spec, "Paste Position", ButtonAction::PastePosition);
+            spawn_text(parent, &font_spec, "", 15.0)
+                .insert(PositionStatusText);
+            spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+        });
+}
+
+fn sandbox_text(
+    sandbox: Res<Sandbox>,
+    mut texts: Query<&mut Text, With<SandboxSizeText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!("{0}x{0}", sandbox.board_size);
+    }
+}
+
+fn code_status_text(
+    code_entry: Res<CodeEntry>,
+    mut texts: Query<&mut Text, With<CodeStatusText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = code_entry.status.clone();
+    }
+}
+
+fn position_status_text(
+    status: Res<PositionStatus>,
+    mut texts: Query<&mut Text, With<PositionStatusText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = status.0.clone();
     }
 }